@@ -0,0 +1,83 @@
+use std::borrow::Cow;
+
+const ESC: char = '\u{1B}';
+const BEL: char = '\u{07}';
+
+/// Length in bytes of the ANSI escape sequence at the start of `s`, or 0 if
+/// there is none. Covers CSI sequences (colors, cursor movement), OSC
+/// sequences (titles, hyperlinks) ended by BEL or ST, and two-character
+/// escapes.
+pub fn sequence_len(s: &str) -> usize {
+    let mut chars = s.char_indices();
+    if !matches!(chars.next(), Some((_, ESC))) {
+        return 0;
+    }
+    match chars.next() {
+        Some((_, '[')) => {
+            // Parameter and intermediate bytes, then one final byte
+            for (i, c) in chars {
+                if ('\u{40}'..='\u{7E}').contains(&c) {
+                    return i + 1;
+                }
+                if !('\u{20}'..='\u{3F}').contains(&c) {
+                    return i;
+                }
+            }
+            s.len()
+        }
+        Some((_, ']')) => {
+            let mut prev = None;
+            for (i, c) in chars {
+                if c == BEL {
+                    return i + 1;
+                }
+                if prev == Some(ESC) && c == '\\' {
+                    return i + 1;
+                }
+                prev = Some(c);
+            }
+            s.len()
+        }
+        Some((i, c)) => i + c.len_utf8(),
+        None => s.len(),
+    }
+}
+
+/// `s` without its ANSI escape sequences.
+pub fn strip(s: &str) -> Cow<'_, str> {
+    if !s.contains(ESC) {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find(ESC) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        rest = &rest[sequence_len(rest).max(1)..];
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// A piece of text as a terminal shows it.
+pub enum Token<'a> {
+    Char(char),      // a character, which may take up columns
+    Escape(&'a str), // an escape sequence, which takes none
+}
+
+/// The characters and escape sequences of `s`, with their byte offsets.
+pub fn tokens(s: &str) -> impl Iterator<Item = (usize, Token<'_>)> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let rest = &s[offset..];
+        let start = offset;
+        let len = sequence_len(rest);
+        if len > 0 {
+            offset += len;
+            return Some((start, Token::Escape(&rest[..len])));
+        }
+        let c = rest.chars().next()?;
+        offset += c.len_utf8();
+        Some((start, Token::Char(c)))
+    })
+}
@@ -0,0 +1,49 @@
+use crate::Section;
+
+/// What a [`SectionClassifier`] makes of a line.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Classified {
+    Delimiter(Section), // a marker starting the section, shown as an empty line
+    Line(Section),      // text in the section, starting it if it is not the current one
+}
+
+/// Decides where the header, body and footer of each logical page begin.
+pub trait SectionClassifier {
+    /// Classify `line` (without its terminator), read while in `current`.
+    fn classify(&mut self, line: &[u8], current: Section) -> Classified;
+}
+
+/// `nl`'s own delimiter lines: the pair three times for a header, twice
+/// for a body and once for a footer. The classifier used unless another is
+/// given.
+#[derive(Clone, Debug)]
+pub struct Delimiters {
+    pair: Vec<u8>, // the delimiter pair, encoded
+}
+
+impl Delimiters {
+    pub fn new(pair: [char; 2]) -> Self {
+        Delimiters {
+            pair: pair.iter().collect::<String>().into_bytes(),
+        }
+    }
+}
+
+impl SectionClassifier for Delimiters {
+    fn classify(&mut self, line: &[u8], current: Section) -> Classified {
+        match delimiter_repeats(line, &self.pair) {
+            Some(3) => Classified::Delimiter(Section::Header),
+            Some(2) => Classified::Delimiter(Section::Body),
+            Some(1) => Classified::Delimiter(Section::Footer),
+            _ => Classified::Line(current),
+        }
+    }
+}
+
+/// How many times `line` repeats the delimiter `pair`, if it consists of
+/// nothing else.
+pub fn delimiter_repeats(line: &[u8], pair: &[u8]) -> Option<usize> {
+    // A short last chunk cannot equal the pair
+    let whole = !line.is_empty() && !pair.is_empty() && line.len().is_multiple_of(pair.len());
+    (whole && line.chunks(pair.len()).all(|chunk| chunk == pair)).then(|| line.len() / pair.len())
+}
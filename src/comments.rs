@@ -0,0 +1,138 @@
+/// The comment markers of a source language.
+#[derive(Clone, Default)]
+pub struct CommentSyntax {
+    pub line: Vec<String>,
+    pub block: Vec<(String, String)>,
+}
+
+impl CommentSyntax {
+    /// Comment syntax for a language name or common file extension.
+    pub fn for_language(name: &str) -> Option<CommentSyntax> {
+        let (line, block): (&[&str], &[(&str, &str)]) = match name.to_ascii_lowercase().as_str() {
+            "rust" | "rs" | "c" | "h" | "cpp" | "c++" | "cc" | "hpp" | "java" | "js"
+            | "javascript" | "ts" | "typescript" | "go" | "cs" | "csharp" | "swift" | "kotlin"
+            | "kt" | "scala" | "dart" => (&["//"], &[("/*", "*/")]),
+            "py" | "python" | "sh" | "bash" | "zsh" | "rb" | "ruby" | "pl" | "perl" | "r"
+            | "yaml" | "yml" | "toml" | "make" | "makefile" | "cmake" | "ps1" | "powershell" => {
+                (&["#"], &[])
+            }
+            "sql" => (&["--"], &[("/*", "*/")]),
+            "haskell" | "hs" => (&["--"], &[("{-", "-}")]),
+            "lua" => (&["--"], &[("--[[", "]]")]),
+            "html" | "xml" | "svg" => (&[], &[("<!--", "-->")]),
+            "css" => (&[], &[("/*", "*/")]),
+            "lisp" | "clojure" | "scheme" | "el" | "asm" | "ini" => (&[";"], &[]),
+            "bat" | "cmd" => (&["rem ", "REM ", "::"], &[]),
+            "tex" | "latex" | "erlang" | "erl" => (&["%"], &[]),
+            "vim" => (&["\""], &[]),
+            _ => return None,
+        };
+        Some(CommentSyntax {
+            line: line.iter().map(|s| s.to_string()).collect(),
+            block: block
+                .iter()
+                .map(|(start, end)| (start.to_string(), end.to_string()))
+                .collect(),
+        })
+    }
+}
+
+/// Classifies lines of source as code or comment-only, tracking block
+/// comments across lines. String literals are not parsed, so a comment
+/// marker inside a string on a code line can confuse block tracking.
+pub struct CommentScanner {
+    syntax: CommentSyntax,
+    /// End marker of the block comment we are inside, if any.
+    open_block: Option<String>,
+}
+
+impl CommentScanner {
+    pub fn new(syntax: CommentSyntax) -> Self {
+        CommentScanner {
+            syntax,
+            open_block: None,
+        }
+    }
+
+    /// Forget any open block comment, e.g. at a section boundary.
+    pub fn reset(&mut self) {
+        self.open_block = None;
+    }
+
+    /// Whether `line` contains code, i.e. is neither blank nor comment-only.
+    pub fn has_code(&mut self, line: &str) -> bool {
+        let mut rest = line;
+        let mut code = false;
+        loop {
+            if let Some(end) = &self.open_block {
+                match rest.find(end.as_str()) {
+                    Some(i) => {
+                        rest = &rest[i + end.len()..];
+                        self.open_block = None;
+                    }
+                    None => return code,
+                }
+            }
+            rest = rest.trim_start();
+            // Block markers first: Lua's --[[ also starts with its line marker --
+            if let Some((start, end)) = self
+                .syntax
+                .block
+                .iter()
+                .find(|(start, _)| rest.starts_with(start.as_str()))
+            {
+                rest = &rest[start.len()..];
+                self.open_block = Some(end.clone());
+                continue;
+            }
+            if rest.is_empty()
+                || self
+                    .syntax
+                    .line
+                    .iter()
+                    .any(|p| rest.starts_with(p.as_str()))
+            {
+                return code;
+            }
+            code = true;
+            // Keep scanning the code for a comment that starts after it
+            let next = rest.char_indices().skip(1).map(|(i, _)| i).find(|&i| {
+                let tail = &rest[i..];
+                self.syntax
+                    .line
+                    .iter()
+                    .any(|p| tail.starts_with(p.as_str()))
+                    || self
+                        .syntax
+                        .block
+                        .iter()
+                        .any(|(s, _)| tail.starts_with(s.as_str()))
+            });
+            match next {
+                Some(i) => rest = &rest[i..],
+                None => return true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn haskell_block_comments_span_lines() {
+        let syntax = CommentSyntax::for_language("haskell").unwrap();
+        let mut scanner = CommentScanner::new(syntax);
+        let lines = [
+            "{- A module",
+            "   comment /* not SQL */",
+            "-}",
+            "main = print 1 -- trailing",
+            "-- a line comment",
+            "x = 2 {- inline -}",
+        ];
+        let code: Vec<bool> = lines.iter().map(|line| scanner.has_code(line)).collect();
+        assert_eq!(code, [false, false, false, true, false, true]);
+    }
+}
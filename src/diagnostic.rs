@@ -0,0 +1,107 @@
+use crate::json::{JsonObject, quote};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by `--errors=json`.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Report diagnostics as JSON objects from now on.
+pub fn use_json() {
+    JSON.store(true, Ordering::Relaxed);
+}
+
+/// Whether diagnostics are reported as JSON.
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// An I/O error about the file at `path`.
+#[derive(Debug)]
+struct PathError {
+    path: String,
+    source: io::Error,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.source)
+    }
+}
+
+impl Error for PathError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Attach `path` to `e`, keeping its kind.
+pub fn with_path(e: io::Error, path: &str) -> io::Error {
+    let kind = e.kind();
+    io::Error::new(
+        kind,
+        PathError {
+            path: path.to_string(),
+            source: e,
+        },
+    )
+}
+
+/// The code, path and message reported for `e`.
+fn describe(e: &io::Error) -> (&'static str, Option<&str>, String) {
+    let code = match e.kind() {
+        io::ErrorKind::NotFound => "not-found",
+        io::ErrorKind::PermissionDenied => "permission-denied",
+        io::ErrorKind::InvalidData => "invalid-data",
+        io::ErrorKind::OutOfMemory => "too-long",
+        _ => "io",
+    };
+    match e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<PathError>())
+    {
+        Some(inner) => (code, Some(&inner.path), inner.source.to_string()),
+        None => (code, None, e.to_string()),
+    }
+}
+
+/// Report an error `e` that ends the run.
+pub fn report_error(e: &io::Error) {
+    let (code, path, message) = describe(e);
+    report(code, path, None, &message);
+}
+
+/// `e` as the JSON object `--errors=json` would report.
+pub fn error_json(e: &io::Error) -> String {
+    let (code, path, message) = describe(e);
+    to_json(code, path, None, &message)
+}
+
+fn to_json(code: &str, path: Option<&str>, line: Option<usize>, message: &str) -> String {
+    JsonObject::new()
+        .string("code", code)
+        .nullable("path", path.map(quote))
+        .nullable("line", line)
+        .string("message", message)
+        .finish()
+}
+
+/// Write a diagnostic to stderr: `nl: PATH:LINE: MESSAGE` as text, or a
+/// JSON object with `code`, `path`, `line` and `message` members, absent
+/// parts being `null`.
+pub fn report(code: &str, path: Option<&str>, line: Option<usize>, message: &str) {
+    if is_json() {
+        eprintln!("{}", to_json(code, path, line, message));
+        return;
+    }
+    let mut location = String::new();
+    if let Some(path) = path {
+        location.push_str(path);
+        if let Some(line) = line {
+            location.push_str(&format!(":{line}"));
+        }
+        location.push_str(": ");
+    }
+    eprintln!("nl: {location}{message}");
+}
@@ -0,0 +1,297 @@
+use crate::shift_jis;
+use std::io::{self, BufRead, Read, Write};
+
+/// A character encoding for input or output text.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Auto, // auto: BOM, else UTF-8 if the first block is valid, else Latin-1
+    Utf8,
+    Latin1,
+    Utf16Le,
+    Utf16Be,
+    ShiftJis, // Windows code page 932, as encoding_rs and browsers read it
+}
+
+impl Encoding {
+    /// Look up an encoding by its command-line name.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "auto" => Some(Encoding::Auto),
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Encoding::Latin1),
+            "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "shift-jis" | "sjis" | "cp932" | "windows-31j" | "ms-kanji" | "x-sjis" => {
+                Some(Encoding::ShiftJis)
+            }
+            _ => None,
+        }
+    }
+
+    fn bom(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => b"\xEF\xBB\xBF",
+            Encoding::Utf16Le => b"\xFF\xFE",
+            Encoding::Utf16Be => b"\xFE\xFF",
+            Encoding::Auto | Encoding::Latin1 | Encoding::ShiftJis => b"",
+        }
+    }
+
+    /// Whether the encoding has a byte order mark, for `--keep-bom`.
+    pub fn has_bom(self) -> bool {
+        !self.bom().is_empty()
+    }
+
+    pub fn is_utf16(self) -> bool {
+        matches!(self, Encoding::Utf16Le | Encoding::Utf16Be)
+    }
+}
+
+/// Determine the encoding of `buf`, consuming a byte order mark if one is
+/// present. Returns the encoding to decode with and whether a BOM was found.
+///
+/// A BOM overrides UTF-8 (the default) and `auto`; an explicit UTF-16
+/// encoding only strips its own BOM, and Latin-1 and Shift_JIS never have
+/// one.
+pub fn detect(buf: &mut impl BufRead, requested: Encoding) -> io::Result<(Encoding, bool)> {
+    let block = buf.fill_buf()?;
+    let candidates: &[Encoding] = match requested {
+        Encoding::Auto | Encoding::Utf8 => &[Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be],
+        Encoding::Utf16Le => &[Encoding::Utf16Le],
+        Encoding::Utf16Be => &[Encoding::Utf16Be],
+        Encoding::Latin1 | Encoding::ShiftJis => &[],
+    };
+    if let Some(&found) = candidates.iter().find(|e| block.starts_with(e.bom())) {
+        buf.consume(found.bom().len());
+        return Ok((found, true));
+    }
+    if requested != Encoding::Auto {
+        return Ok((requested, false));
+    }
+    // A multibyte sequence cut off at the end of the block is still UTF-8
+    let utf8 = match std::str::from_utf8(block) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    let guess = if utf8 {
+        Encoding::Utf8
+    } else {
+        Encoding::Latin1
+    };
+    Ok((guess, false))
+}
+
+fn push_char(out: &mut Vec<u8>, c: char) {
+    let mut utf8 = [0; 4];
+    out.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+}
+
+/// Adapter that decodes Latin-1, UTF-16 or Shift_JIS input and yields it as
+/// UTF-8. Unpaired surrogates, a dangling odd byte and bytes Shift_JIS has
+/// no character for become U+FFFD.
+pub struct DecodingReader<R> {
+    inner: R,
+    encoding: Encoding,
+    raw: Vec<u8>,
+    decoded: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> DecodingReader<R> {
+    pub fn new(inner: R, encoding: Encoding) -> Self {
+        DecodingReader {
+            inner,
+            encoding,
+            raw: Vec::new(),
+            decoded: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Decode the next block of input into `decoded`.
+    /// Returns false once the input is exhausted.
+    fn fill_decoded(&mut self) -> io::Result<bool> {
+        let block = self.inner.fill_buf()?;
+        let eof = block.is_empty();
+        let len = block.len();
+        self.raw.extend_from_slice(block);
+        self.inner.consume(len);
+
+        if self.encoding == Encoding::ShiftJis {
+            self.decode_shift_jis(eof);
+            return Ok(!eof || !self.decoded.is_empty());
+        }
+        if !self.encoding.is_utf16() {
+            for &b in &self.raw {
+                push_char(&mut self.decoded, char::from(b));
+            }
+            self.raw.clear();
+            return Ok(!eof);
+        }
+
+        let little_endian = self.encoding == Encoding::Utf16Le;
+        let mut units: Vec<u16> = self
+            .raw
+            .chunks_exact(2)
+            .map(|pair| {
+                if little_endian {
+                    u16::from_le_bytes([pair[0], pair[1]])
+                } else {
+                    u16::from_be_bytes([pair[0], pair[1]])
+                }
+            })
+            .collect();
+        // Keep an odd trailing byte and a high surrogate for the next block
+        let mut keep = self.raw.len() % 2;
+        if !eof && matches!(units.last(), Some(0xD800..=0xDBFF)) {
+            units.pop();
+            keep += 2;
+        }
+
+        for c in char::decode_utf16(units) {
+            push_char(&mut self.decoded, c.unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+        self.raw.drain(..self.raw.len() - keep);
+
+        if eof && !self.raw.is_empty() {
+            self.raw.clear();
+            push_char(&mut self.decoded, char::REPLACEMENT_CHARACTER);
+        }
+        Ok(!eof || !self.decoded.is_empty())
+    }
+
+    /// Decode the Shift_JIS in `raw`, keeping a lead byte at its end for
+    /// the next block unless the input is exhausted. As in the Encoding
+    /// Standard, an ASCII byte after a lead byte is kept when the two do not
+    /// make a character.
+    fn decode_shift_jis(&mut self, eof: bool) {
+        let mut i = 0;
+        while i < self.raw.len() {
+            let b = self.raw[i];
+            if !shift_jis::is_lead(b) {
+                let c = shift_jis::decode_single(b).unwrap_or(char::REPLACEMENT_CHARACTER);
+                push_char(&mut self.decoded, c);
+                i += 1;
+                continue;
+            }
+            let Some(&trail) = self.raw.get(i + 1) else {
+                if eof {
+                    push_char(&mut self.decoded, char::REPLACEMENT_CHARACTER);
+                    i += 1;
+                }
+                break;
+            };
+            match shift_jis::decode_pair(b, trail) {
+                Some(c) => {
+                    push_char(&mut self.decoded, c);
+                    i += 2;
+                }
+                None => {
+                    push_char(&mut self.decoded, char::REPLACEMENT_CHARACTER);
+                    i += if trail.is_ascii() { 1 } else { 2 };
+                }
+            }
+        }
+        self.raw.drain(..i);
+    }
+}
+
+impl<R: BufRead> Read for DecodingReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            self.decoded.clear();
+            self.pos = 0;
+            if !self.fill_decoded()? {
+                return Ok(0);
+            }
+        }
+        let n = out.len().min(self.decoded.len() - self.pos);
+        out[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Adapter that re-encodes the UTF-8 written through it.
+/// UTF-8 passes straight through; characters Latin-1 or Shift_JIS cannot
+/// represent become `?`.
+pub struct EncodingWriter<W> {
+    inner: W,
+    encoding: Encoding,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    pub fn new(inner: W, encoding: Encoding) -> Self {
+        EncodingWriter {
+            inner,
+            encoding,
+            pending: Vec::new(),
+        }
+    }
+
+    fn encode(&mut self, text: &str) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            match self.encoding {
+                Encoding::Latin1 => bytes.push(u8::try_from(c).unwrap_or(b'?')),
+                Encoding::ShiftJis => {
+                    if !shift_jis::encode(c, &mut bytes) {
+                        bytes.push(b'?');
+                    }
+                }
+                Encoding::Utf16Le | Encoding::Utf16Be => {
+                    let mut units = [0; 2];
+                    for unit in c.encode_utf16(&mut units) {
+                        if self.encoding == Encoding::Utf16Le {
+                            bytes.extend_from_slice(&unit.to_le_bytes());
+                        } else {
+                            bytes.extend_from_slice(&unit.to_be_bytes());
+                        }
+                    }
+                }
+                Encoding::Auto | Encoding::Utf8 => push_char(&mut bytes, c),
+            }
+        }
+        self.inner.write_all(&bytes)
+    }
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if matches!(self.encoding, Encoding::Auto | Encoding::Utf8) {
+            return self.inner.write(buf);
+        }
+        self.pending.extend_from_slice(buf);
+        loop {
+            let pending = std::mem::take(&mut self.pending);
+            match std::str::from_utf8(&pending) {
+                Ok(text) => {
+                    self.encode(text)?;
+                    break;
+                }
+                Err(e) => {
+                    let (valid, rest) = pending.split_at(e.valid_up_to());
+                    // Safe to unwrap: `valid_up_to` marks the end of valid UTF-8
+                    self.encode(std::str::from_utf8(valid).unwrap())?;
+                    match e.error_len() {
+                        Some(bad) => {
+                            self.encode("\u{FFFD}")?;
+                            self.pending = rest[bad..].to_vec();
+                        }
+                        // Incomplete sequence: wait for the rest of it
+                        None => {
+                            self.pending = rest.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
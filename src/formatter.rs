@@ -0,0 +1,22 @@
+use crate::Format;
+
+/// Renders line numbers, for numbering the formats cannot express, such
+/// as roman numerals or ordinals.
+pub trait NumberFormatter {
+    /// `number` as it goes in front of its line. The field `-w` asks for is
+    /// `width` columns; the text may be wider.
+    fn format(&self, number: i64, width: usize) -> String;
+}
+
+/// The `-n` formats. The one in `Numbering` is used unless another
+/// formatter is given.
+impl NumberFormatter for Format {
+    fn format(&self, number: i64, width: usize) -> String {
+        match self {
+            Format::Left => format!("{number:<width$}"),
+            Format::Right => format!("{number:>width$}"),
+            Format::RightZero => format!("{number:>0width$}"),
+            Format::Center => format!("{number:^width$}"),
+        }
+    }
+}
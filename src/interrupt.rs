@@ -0,0 +1,97 @@
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit status for a run cut short by Ctrl+C (128 + SIGINT).
+pub const EXIT_STATUS: i32 = 130;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether Ctrl+C has been pressed.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// The error used to unwind processing once an interrupt is requested.
+/// Letting it propagate drops, and so flushes, the buffered output.
+pub fn error() -> io::Error {
+    io::Error::other("interrupted")
+}
+
+/// Catch Ctrl+C instead of dying with output still buffered.
+#[cfg(unix)]
+pub fn install() {
+    use std::ffi::c_int;
+
+    const SIGINT: c_int = 2;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn siginterrupt(signum: c_int, flag: c_int) -> c_int;
+    }
+
+    extern "C" fn on_sigint(_: c_int) {
+        REQUESTED.store(true, Ordering::Relaxed);
+    }
+
+    // SAFETY: the handler only stores to an atomic, which is signal-safe.
+    // siginterrupt makes a blocked read fail with EINTR rather than restart.
+    unsafe {
+        signal(SIGINT, on_sigint);
+        siginterrupt(SIGINT, 1);
+    }
+}
+
+/// Catch Ctrl+C instead of dying with output still buffered.
+#[cfg(windows)]
+pub fn install() {
+    const CTRL_C_EVENT: u32 = 0;
+    const CTRL_BREAK_EVENT: u32 = 1;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    // Runs on its own thread; a console read in progress returns no data
+    extern "system" fn on_ctrl(ctrl_type: u32) -> i32 {
+        if ctrl_type == CTRL_C_EVENT || ctrl_type == CTRL_BREAK_EVENT {
+            REQUESTED.store(true, Ordering::Relaxed);
+            1
+        } else {
+            0
+        }
+    }
+
+    // SAFETY: registers a handler with the signature the API expects
+    unsafe {
+        SetConsoleCtrlHandler(on_ctrl, 1);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn install() {}
+
+/// Reader adapter that stops reading once an interrupt is requested, even if
+/// it arrives while blocked in a read: `BufRead` retries `Interrupted` reads,
+/// so those are turned into the non-retried [`error`].
+pub struct InterruptibleReader<R> {
+    inner: R,
+}
+
+impl<R> InterruptibleReader<R> {
+    pub fn new(inner: R) -> Self {
+        InterruptibleReader { inner }
+    }
+}
+
+impl<R: Read> Read for InterruptibleReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if requested() {
+            return Err(error());
+        }
+        match self.inner.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted && requested() => Err(error()),
+            result => result,
+        }
+    }
+}
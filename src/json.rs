@@ -0,0 +1,119 @@
+pub use nl::{JsonObject, quote};
+use std::io::{self, BufRead};
+
+/// Streams the elements of a top-level JSON array, one at a time, without
+/// parsing them into values. Each element is returned as compact JSON text
+/// (whitespace outside strings removed). Only the structure needed to find
+/// element boundaries is checked; elements themselves are not validated.
+pub struct ArrayElements<R> {
+    inner: R,
+    started: bool,
+    after_comma: bool,
+    done: bool,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid JSON array: {message}"),
+    )
+}
+
+impl<R: BufRead> ArrayElements<R> {
+    pub fn new(inner: R) -> Self {
+        ArrayElements {
+            inner,
+            started: false,
+            after_comma: false,
+            done: false,
+        }
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let block = self.inner.fill_buf()?;
+        let Some(&b) = block.first() else {
+            return Ok(None);
+        };
+        self.inner.consume(1);
+        Ok(Some(b))
+    }
+
+    fn next_non_whitespace(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            match self.next_byte()? {
+                Some(b' ' | b'\t' | b'\n' | b'\r') => continue,
+                other => return Ok(other),
+            }
+        }
+    }
+
+    fn next_element(&mut self) -> io::Result<Option<String>> {
+        if self.done {
+            return Ok(None);
+        }
+        if !self.started {
+            self.started = true;
+            if self.next_non_whitespace()? != Some(b'[') {
+                return Err(invalid("input does not start with '['"));
+            }
+        }
+
+        let mut element = Vec::new();
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        loop {
+            let b = if element.is_empty() {
+                self.next_non_whitespace()?
+            } else {
+                self.next_byte()?
+            };
+            let Some(b) = b else {
+                return Err(invalid("unexpected end of input"));
+            };
+            if in_string {
+                element.push(b);
+                match b {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match b {
+                b' ' | b'\t' | b'\n' | b'\r' => continue,
+                b',' | b']' if depth == 0 => {
+                    if b == b']' {
+                        self.done = true;
+                        if self.next_non_whitespace()?.is_some() {
+                            return Err(invalid("trailing data after ']'"));
+                        }
+                        if element.is_empty() && !self.after_comma {
+                            return Ok(None);
+                        }
+                    }
+                    if element.is_empty() {
+                        return Err(invalid("missing element"));
+                    }
+                    self.after_comma = b == b',';
+                    return Ok(Some(String::from_utf8_lossy(&element).into_owned()));
+                }
+                b'[' | b'{' => depth += 1,
+                b']' | b'}' if depth > 0 => depth -= 1,
+                b'}' => return Err(invalid("unbalanced '}'")),
+                b'"' => in_string = true,
+                _ => {}
+            }
+            element.push(b);
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ArrayElements<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_element().transpose()
+    }
+}
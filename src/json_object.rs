@@ -0,0 +1,65 @@
+use std::fmt::{Display, Write};
+
+/// `s` as a JSON string literal.
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A JSON object built up member by member, in order. Everything nl writes
+/// as JSON goes through it, so member names and strings are quoted the same
+/// way everywhere.
+pub struct JsonObject(String);
+
+impl JsonObject {
+    pub fn new() -> Self {
+        JsonObject(String::from("{"))
+    }
+
+    /// Add member `name` with `value`, which is JSON already: a number, a
+    /// literal, or an array or object written out.
+    pub fn raw(mut self, name: &str, value: impl Display) -> Self {
+        if self.0.len() > 1 {
+            self.0.push(',');
+        }
+        write!(self.0, "{}:{value}", quote(name)).expect("writing to a String succeeds");
+        self
+    }
+
+    /// Add member `name` with the string `value`.
+    pub fn string(self, name: &str, value: &str) -> Self {
+        self.raw(name, quote(value))
+    }
+
+    /// Add member `name` with `value`, or `null` if there is none.
+    pub fn nullable(self, name: &str, value: Option<impl Display>) -> Self {
+        match value {
+            Some(value) => self.raw(name, value),
+            None => self.raw(name, "null"),
+        }
+    }
+
+    /// The object's text.
+    pub fn finish(mut self) -> String {
+        self.0.push('}');
+        self.0
+    }
+}
+
+impl Default for JsonObject {
+    fn default() -> Self {
+        JsonObject::new()
+    }
+}
@@ -0,0 +1,266 @@
+//! Line numbering in the manner of POSIX `nl`, for Rust programs that want
+//! numbered output without running the command.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+mod classifier;
+mod formatter;
+mod json_object;
+#[cfg(not(feature = "regex"))]
+#[doc(hidden)]
+pub mod no_regex;
+mod policy;
+mod reader;
+mod stream;
+mod writer;
+
+pub use classifier::{Classified, Delimiters, SectionClassifier, delimiter_repeats};
+pub use formatter::NumberFormatter;
+// Shared with the command, which writes all its JSON the same way
+#[doc(hidden)]
+pub use json_object::{JsonObject, quote};
+pub use policy::{Decision, NumberingPolicy, SectionStyles};
+pub use reader::LineNumberingReader;
+pub use stream::{Stream, stream};
+pub use writer::LineNumberingWriter;
+
+#[cfg(not(feature = "regex"))]
+use no_regex as regex;
+use regex::Regex;
+
+/// Which lines of a section get a number.
+#[derive(Clone, Debug)]
+pub enum Style {
+    All,             // a: every line
+    NonEmpty,        // t: lines with any text
+    None,            // n: no lines
+    Pattern(Regex),  // pBRE: lines matching the regex
+    And(Vec<Style>), // S1&S2: lines every style numbers
+    Or(Vec<Style>),  // S1|S2: lines any style numbers
+    Not(Box<Style>), // !S: lines the style does not number
+}
+
+impl Style {
+    /// The `p` style regexes in the style, however deep in an expression.
+    pub fn patterns(&self) -> Vec<&Regex> {
+        match self {
+            Style::Pattern(re) => vec![re],
+            Style::And(styles) | Style::Or(styles) => {
+                styles.iter().flat_map(Style::patterns).collect()
+            }
+            Style::Not(style) => style.patterns(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// How a number is laid out in its field.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Left,      // ln: left-justified
+    Right,     // rn: right-justified
+    RightZero, // rz: right-justified with leading zeros
+    Center,    // cn: centered, any odd space on the right
+}
+
+/// The parts of a logical page, switched between by delimiter lines.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Section {
+    Header,
+    Body,
+    Footer,
+    Level(usize), // a further kind of section, by position among them
+}
+
+impl Section {
+    /// The section's name: `header`, `body`, `footer` or `level`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Section::Header => "header",
+            Section::Body => "body",
+            Section::Footer => "footer",
+            Section::Level(_) => "level",
+        }
+    }
+}
+
+/// How to number lines. The default numbers like `nl` with no options.
+#[derive(Clone, Debug)]
+pub struct Numbering {
+    pub header: Style,        // -h
+    pub body: Style,          // -b
+    pub footer: Style,        // -f
+    pub format: Format,       // -n
+    pub width: usize,         // -w
+    pub separator: String,    // -s
+    pub start: i64,           // -v
+    pub increment: i64,       // -i
+    pub delimiter: [char; 2], // -d
+    pub renumber: bool,       // restart at each section, unless -p
+    pub join_blank: usize,    // -l
+}
+
+impl Default for Numbering {
+    fn default() -> Self {
+        Numbering {
+            header: Style::None,
+            body: Style::NonEmpty,
+            footer: Style::None,
+            format: Format::Right,
+            width: 6,
+            separator: "\t".to_string(),
+            start: 1,
+            increment: 1,
+            delimiter: ['\\', ':'],
+            renumber: true,
+            join_blank: 1,
+        }
+    }
+}
+
+/// A line as it was numbered. Hooks and the borrowing iterators get its
+/// text borrowed; [`Stream`] yields it owned.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NumberedLine<'a> {
+    pub text: Cow<'a, [u8]>, // the line, without its terminator
+    pub index: u64,          // its place in the input, from 0
+    pub offset: u64,         // the byte offset it starts at in the input
+    pub section: Section,    // the section it is in
+    pub number: Option<i64>, // the number it was given, if any
+}
+
+impl NumberedLine<'_> {
+    /// The line with its text copied, so it outlives the input buffer.
+    pub fn into_owned(self) -> NumberedLine<'static> {
+        NumberedLine {
+            text: Cow::Owned(self.text.into_owned()),
+            ..self
+        }
+    }
+
+    /// The line as a JSON object, a stable schema for tools reading numbered
+    /// lines: `{"number":1,"section":"body","index":0,"offset":0,"text":"..."}`,
+    /// with a `number` of `null` for unnumbered lines. Invalid UTF-8 in the
+    /// text is replaced with U+FFFD.
+    pub fn to_json(&self) -> String {
+        JsonObject::new()
+            .nullable("number", self.number)
+            .string("section", self.section.name())
+            .raw("index", self.index)
+            .raw("offset", self.offset)
+            .string("text", &String::from_utf8_lossy(&self.text))
+            .finish()
+    }
+}
+
+/// Called with each line once it is numbered.
+type Observer = Box<dyn FnMut(&NumberedLine) + Send>;
+
+/// Numbering carried from one line to the next.
+struct Numberer {
+    numbering: Numbering,
+    policy: Box<dyn NumberingPolicy + Send>,
+    observer: Option<Observer>,
+    classifier: Box<dyn SectionClassifier + Send>,
+    formatter: Box<dyn NumberFormatter + Send>,
+    section: Section,
+    next: i64,
+    index: u64,       // input lines seen so far
+    offset: u64,      // input bytes seen so far
+    blank_run: usize, // blank lines in a row, for -l
+}
+
+impl Numberer {
+    fn new(numbering: Numbering, policy: Box<dyn NumberingPolicy + Send>) -> Self {
+        let classifier = Box::new(Delimiters::new(numbering.delimiter));
+        let formatter = Box::new(numbering.format);
+        Numberer {
+            next: numbering.start,
+            numbering,
+            policy,
+            observer: None,
+            classifier,
+            formatter,
+            section: Section::Body,
+            index: 0,
+            offset: 0,
+            blank_run: 0,
+        }
+    }
+
+    /// Classify and number `line`, which took `raw_len` bytes of input with
+    /// its terminator. A delimiter line switches section and gives `None`.
+    /// Numbering restarts with each section unless `-p`. With `-l`, a blank
+    /// line the policy numbers keeps its number only if it ends a run of
+    /// `join_blank` blank lines.
+    fn number<'a>(&mut self, line: &'a [u8], raw_len: usize) -> Option<NumberedLine<'a>> {
+        let index = self.index;
+        let offset = self.offset;
+        self.index += 1;
+        self.offset += raw_len as u64;
+        let (section, delimiter) = match self.classifier.classify(line, self.section) {
+            Classified::Delimiter(section) => (section, true),
+            Classified::Line(section) => (section, false),
+        };
+        if delimiter || section != self.section {
+            self.section = section;
+            if self.numbering.renumber {
+                self.next = self.numbering.start;
+            }
+        }
+        if delimiter {
+            self.blank_run = 0;
+            return None;
+        }
+        let mut number = match self.policy.decide(line, index, section) {
+            Decision::Skip => None,
+            Decision::Next => Some(self.next),
+            Decision::Number(n) => Some(n),
+        };
+        if !line.is_empty() {
+            self.blank_run = 0;
+        } else {
+            self.blank_run += 1;
+            if self.blank_run < self.numbering.join_blank {
+                number = None;
+            } else if number.is_some() {
+                self.blank_run = 0;
+            }
+        }
+        if let Some(n) = number {
+            self.next = n + self.numbering.increment;
+        }
+        Some(NumberedLine {
+            text: Cow::Borrowed(line),
+            index,
+            offset,
+            section,
+            number,
+        })
+    }
+
+    /// Write `line`, without its terminator, numbered to `out`, then pass it
+    /// to the observer. A delimiter line is written as an empty line.
+    fn write_line(&mut self, line: &[u8], raw_len: usize, out: &mut impl Write) -> io::Result<()> {
+        let Some(numbered) = self.number(line, raw_len) else {
+            return Ok(());
+        };
+        let numbering = &self.numbering;
+        let width = numbering.width;
+        match numbered.number {
+            Some(n) => write!(
+                out,
+                "{}{}",
+                self.formatter.format(n, width),
+                numbering.separator
+            )?,
+            None => write!(out, "{:width$}", "")?,
+        }
+        out.write_all(line)?;
+        if let Some(observer) = &mut self.observer {
+            observer(&numbered);
+        }
+        Ok(())
+    }
+}
@@ -1,83 +1,551 @@
+mod ansi;
+mod comments;
+mod diagnostic;
+mod encoding;
+mod interrupt;
+mod json;
+mod normalize;
+mod pager;
+mod progress;
+mod readahead;
+mod shift_jis;
+mod spill;
+mod tee;
+mod terminal;
+mod timestamp;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+mod width;
+
+use comments::{CommentScanner, CommentSyntax};
+use encoding::{DecodingReader, Encoding, EncodingWriter};
+use json::JsonObject;
+#[cfg(not(feature = "regex"))]
+use nl::no_regex as regex;
+use nl::{Format, NumberFormatter, Section, Style, delimiter_repeats};
+use progress::ProgressReader;
 use regex::Regex;
+use spill::SpillBuffer;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
 use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use timestamp::Clock;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Column {
+    Number,   // num: the line number (or padding when unnumbered)
+    Global,   // global: the line number not reset by sections
+    Offset,   // offset: byte offset of the line start
+    Section,  // section: header, body or footer
+    Filename, // filename: name of the input
+    Length,   // length: line length in characters
+    Percent,  // percent: how far through the input the line ends
+    Text,     // text: the line content
+}
+
+#[derive(Clone, PartialEq)]
+enum RecordSeparator {
+    Newline,            // lines ending in \n or \r\n (default)
+    Paragraph,          // empty RS: blocks separated by blank lines
+    Delimiter(Vec<u8>), // records ending in an arbitrary byte string
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MarkdownMode {
+    SkipCode, // skip-code: do not number inside fenced code blocks
+    OnlyCode, // only-code: number only inside fenced code blocks
+}
 
+/// How `--log-entries` recognizes the first line of a log entry.
 #[derive(Clone)]
-enum NumberStyle {
-    All,             // a: number all lines
-    NonEmpty,        // t: number non-empty lines
-    None,            // n: no numbering
-    Pattern(Regex),  // pBRE: number lines matching regex
+enum EntryStart {
+    Unindented,      // lines not starting with whitespace
+    Matching(Regex), // lines matching a regex, e.g. a leading timestamp
 }
 
-#[derive(Clone, Copy)]
-enum NumberFormat {
-    Left,      // ln: left justified
-    Right,     // rn: right justified (default)
-    RightZero, // rz: right justified, leading zeros
+impl EntryStart {
+    fn begins_entry(&self, line: &str) -> bool {
+        match self {
+            EntryStart::Unindented => !line.is_empty() && !line.starts_with(char::is_whitespace),
+            EntryStart::Matching(re) => re.is_match(line),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum UniqueMode {
+    Plain,    // leave repeated lines unnumbered
+    Annotate, // show =N, the number of the first occurrence
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DiffMode {
+    All,   // all: number context and added lines
+    Added, // added: number only added lines
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum JsonOutput {
+    Lines,    // lines: NUMBER SEPARATOR ELEMENT, like numbered lines
+    Annotate, // annotate: one {"number":N,"value":ELEMENT} object per line
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CountMode {
+    Lines, // lines: the line number (default)
+    Bytes, // bytes: byte offset of the line start
+    Chars, // chars: characters before the line start
+    Words, // words: words before the line start
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Overflow {
+    Grow,        // grow: a number wider than -w pushes its line's text right
+    GrowRealign, // grow-realign: widen every line's field to fit the widest
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ContinueFrom {
+    First, // first: the first number already in the input
+    Last,  // last: the last number already in the input
+}
+
+/// What `--strip-ansi` removes escape sequences from.
+#[derive(Clone, Copy, PartialEq)]
+enum StripAnsi {
+    Match,  // match: only the text that blank checks and regexes see (default)
+    Output, // output: that and the content written out
+}
+
+/// Letter case of digits past 9 in bases above 10.
+#[derive(Clone, Copy, PartialEq)]
+enum NumberCase {
+    Lower, // lower: digits past 9 as a-z (default)
+    Upper, // upper: digits past 9 as A-Z
 }
 
+/// Which lines count as blank for style `t` and `-l`.
 #[derive(Clone, Copy, PartialEq)]
-enum Section {
-    Header,
-    Body,
-    Footer,
+enum BlankLines {
+    Empty,      // empty: only lines with nothing on them (default)
+    Whitespace, // whitespace: also lines of nothing but Unicode whitespace
+    Invisible,  // invisible: also lines of whitespace and zero-width characters
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BinaryPolicy {
+    Skip,    // skip: do not number binary input, print a notice
+    Warn,    // warn: print a warning, then number anyway
+    Process, // process: number binary input silently (default)
+}
+
+/// A section kind added with `--section-level`, selected by a delimiter
+/// repeated more times than `--section-map` covers.
+#[derive(Clone)]
+struct SectionLevel {
+    name: String,
+    style: Style,
 }
 
+#[derive(Clone)]
 struct Config {
-    header_style: NumberStyle,
-    body_style: NumberStyle,
-    footer_style: NumberStyle,
-    number_format: NumberFormat,
+    header_style: Style,
+    body_style: Style,
+    footer_style: Style,
+    number_format: Format,
     number_width: usize,
+    base: u32,
+    number_case: NumberCase,
+    zero_digit: char, // `--digits`: the zero of the digit set, 0-9 following it
+    overflow: Overflow,
     separator: String,
     start_number: i64,
     increment: i64,
     join_blank: usize,
+    blank_lines: BlankLines,
+    warn_invisible: bool,
     no_renumber: bool,
     section_delimiter: [char; 2],
-    file: Option<String>,
+    section_map: Vec<Option<Section>>,
+    section_levels: Vec<SectionLevel>,
+    indent_sections: Option<usize>,
+    binary_policy: BinaryPolicy,
+    keep_bom: bool,
+    input_encoding: Encoding,
+    output_encoding: Encoding,
+    tab_stops: Option<Vec<usize>>,
+    show_nonprinting: bool,
+    show_ends: bool,
+    show_tabs: bool,
+    squeeze_blank: bool,
+    sections: bool,
+    pad_unnumbered: bool,
+    count_mode: CountMode,
+    columns: Option<Vec<Column>>,
+    column_separator: String,
+    timestamp: Option<String>,
+    wrap: Option<usize>,
+    right_margin: Option<usize>,
+    content_column: Option<usize>,
+    tabstop: Option<usize>, // `--tabstop`: the terminal's tab interval, to align to
+    global_number: bool,
+    section_labels: Option<[String; 3]>,
+    section_banner: Option<String>,
+    blank_placeholder: Option<String>,
+    max_line_length: Option<usize>,
+    report_length: bool,
+    record_separator: RecordSeparator,
+    paragraphs: bool,
+    join_continuations: bool,
+    csv: bool,
+    json_array: Option<JsonOutput>,
+    markdown: Option<MarkdownMode>,
+    between: Option<(Regex, Regex)>,
+    normalize: Option<normalize::Form>, // form lines are put in before regex matching
+    strip_ansi: Option<StripAnsi>,
+    comments: Option<CommentSyntax>,
+    diff: Option<DiffMode>,
+    log_entries: Option<EntryStart>,
+    min_length: Option<usize>,
+    min_length_trim: bool,
+    unique: Option<UniqueMode>,
+    quiet: bool,
+    debug: bool, // trace section changes and numbering decisions to stderr
+    progress: bool,
+    number_prefix: String,
+    number_suffix: String,
+    line_end: String,
+    keep_line_endings: bool,
+    show_sign: bool,
+    renumber: Option<Regex>,
+    strip: bool,
+    check: bool,
+    total: Option<String>,
+    report: Option<String>,
+    tee: Option<String>,
+    append: bool,
+    fail_fast: bool,  // stop at the first input that cannot be read
+    check_args: bool, // only check the options and that the inputs open
+    copy_safe: bool,  // no option was given that [`copy_through`] cannot honor
+    file_totals: Option<String>,
+    pager: Option<String>,
+    continue_from: Option<ContinueFrom>,
+    threads: usize,
+    restart_per_file: bool, // number each file from -v rather than as one stream
+    match_threads: usize,   // threads trying `p` style regexes on lines read ahead
+    max_memory: Option<usize>,
+    drop_cache: bool,
+    files: Vec<String>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
-            header_style: NumberStyle::None,
-            body_style: NumberStyle::NonEmpty,
-            footer_style: NumberStyle::None,
-            number_format: NumberFormat::Right,
+            header_style: Style::None,
+            body_style: Style::NonEmpty,
+            footer_style: Style::None,
+            number_format: Format::Right,
+            base: 10,
+            number_case: NumberCase::Lower,
+            zero_digit: '0',
+            overflow: Overflow::Grow,
             number_width: 6,
             separator: "\t".to_string(),
             start_number: 1,
             increment: 1,
             join_blank: 1,
+            blank_lines: BlankLines::Empty,
+            warn_invisible: false,
             no_renumber: false,
             section_delimiter: ['\\', ':'],
-            file: None,
+            section_map: vec![
+                Some(Section::Footer),
+                Some(Section::Body),
+                Some(Section::Header),
+            ],
+            section_levels: Vec::new(),
+            indent_sections: None,
+            binary_policy: BinaryPolicy::Process,
+            keep_bom: false,
+            input_encoding: Encoding::Utf8,
+            output_encoding: Encoding::Utf8,
+            tab_stops: None,
+            show_nonprinting: false,
+            show_ends: false,
+            show_tabs: false,
+            squeeze_blank: false,
+            sections: true,
+            pad_unnumbered: true,
+            count_mode: CountMode::Lines,
+            columns: None,
+            column_separator: "\t".to_string(),
+            timestamp: None,
+            wrap: None,
+            right_margin: None,
+            content_column: None,
+            tabstop: None,
+            global_number: false,
+            section_labels: None,
+            section_banner: None,
+            blank_placeholder: None,
+            max_line_length: None,
+            report_length: false,
+            record_separator: RecordSeparator::Newline,
+            paragraphs: false,
+            join_continuations: false,
+            csv: false,
+            json_array: None,
+            markdown: None,
+            between: None,
+            normalize: None,
+            strip_ansi: None,
+            comments: None,
+            diff: None,
+            log_entries: None,
+            min_length: None,
+            min_length_trim: false,
+            unique: None,
+            quiet: false,
+            debug: false,
+            progress: false,
+            number_prefix: String::new(),
+            number_suffix: String::new(),
+            line_end: "\n".to_string(),
+            keep_line_endings: false,
+            show_sign: false,
+            renumber: None,
+            strip: false,
+            check: false,
+            total: None,
+            report: None,
+            tee: None,
+            append: false,
+            fail_fast: false,
+            check_args: false,
+            copy_safe: true,
+            file_totals: None,
+            pager: None,
+            continue_from: None,
+            threads: 1,
+            restart_per_file: false,
+            match_threads: 1,
+            max_memory: None,
+            drop_cache: false,
+            files: Vec::new(),
         }
     }
 }
 
-fn parse_style(value: &str, option: &str) -> NumberStyle {
+/// Parse a numbering style, given by letter (`a`, `t`, `n`, `pBRE`) or by
+/// name (`all`, `nonempty`, `none`, `regex=BRE`), or an expression of them.
+fn parse_style(value: &str, option: &str) -> Style {
+    if let Some(style) = parse_style_expression(value, option) {
+        return style;
+    }
+    let pattern = value
+        .strip_prefix("regex=")
+        .or_else(|| value.strip_prefix('p'));
     match value {
-        "a" => NumberStyle::All,
-        "t" => NumberStyle::NonEmpty,
-        "n" => NumberStyle::None,
-        s if s.starts_with('p') => {
-            let pattern = &s[1..];
-            match Regex::new(pattern) {
-                Ok(re) => NumberStyle::Pattern(re),
-                Err(e) => {
-                    eprintln!("nl: invalid regex for '{option}': {e}");
-                    process::exit(1);
-                }
+        "a" | "all" => Style::All,
+        "t" | "nonempty" => Style::NonEmpty,
+        "n" | "none" => Style::None,
+        _ if let Some(pattern) = pattern => match Regex::new(pattern) {
+            Ok(re) => Style::Pattern(re),
+            Err(e) if !cfg!(feature = "regex") => {
+                usage_error(&format!("style 'p' for '{option}' is not supported: {e}"));
+            }
+            Err(e) => {
+                usage_error(&format!("invalid regex for '{option}': {e}"));
+            }
+        },
+        _ => {
+            usage_error(&format!("invalid numbering style: '{value}'"));
+        }
+    }
+}
+
+/// Parse a style expression such as `t&pWARN` or `n|!pDEBUG`: styles
+/// joined by `&` (binding tighter) and `|`, each maybe negated with `!`.
+/// A `p` term takes the rest of the value as its regex, so `pFOO|BAR` is
+/// one style, as it was before styles combined. `None` unless every part
+/// is a valid style.
+fn parse_style_expression(value: &str, option: &str) -> Option<Style> {
+    if !value.contains(['&', '|']) && !value.starts_with('!') {
+        return None;
+    }
+    // Each term, and whether `&` joins it to the one before
+    let mut terms = Vec::new();
+    let mut rest = value;
+    let mut and = false;
+    loop {
+        let bare = rest.trim_start_matches('!');
+        if bare.starts_with('p') || bare.starts_with("regex=") {
+            terms.push((and, rest));
+            break;
+        }
+        match rest.find(['&', '|']) {
+            Some(i) => {
+                terms.push((and, &rest[..i]));
+                and = rest.as_bytes()[i] == b'&';
+                rest = &rest[i + 1..];
+            }
+            None => {
+                terms.push((and, rest));
+                break;
+            }
+        }
+    }
+    let mut alternatives: Vec<Vec<Style>> = Vec::new();
+    for (and, term) in terms {
+        let style = parse_style_term(term, option)?;
+        match alternatives.last_mut() {
+            Some(alternative) if and => alternative.push(style),
+            _ => alternatives.push(vec![style]),
+        }
+    }
+    let alternatives = alternatives
+        .into_iter()
+        .map(|terms| one_or(terms, Style::And))
+        .collect();
+    Some(one_or(alternatives, Style::Or))
+}
+
+/// The only style in `styles`, or all of them combined by `combine`.
+fn one_or(mut styles: Vec<Style>, combine: fn(Vec<Style>) -> Style) -> Style {
+    if styles.len() == 1 {
+        styles.remove(0)
+    } else {
+        combine(styles)
+    }
+}
+
+/// One term of a style expression, or `None` if it is not a valid style.
+/// A `p` term with a bad regex, or in a build without regex support, is an
+/// error right away, rather than leaving the whole expression to fail as an
+/// unknown style.
+fn parse_style_term(term: &str, option: &str) -> Option<Style> {
+    if let Some(negated) = term.strip_prefix('!') {
+        return Some(Style::Not(Box::new(parse_style_term(negated, option)?)));
+    }
+    let pattern = term
+        .strip_prefix("regex=")
+        .or_else(|| term.strip_prefix('p'));
+    match term {
+        "a" | "all" => Some(Style::All),
+        "t" | "nonempty" => Some(Style::NonEmpty),
+        "n" | "none" => Some(Style::None),
+        _ => match Regex::new(pattern?) {
+            Ok(re) => Some(Style::Pattern(re)),
+            Err(e) if !cfg!(feature = "regex") => {
+                usage_error(&format!("style 'p' for '{option}' is not supported: {e}"));
             }
+            Err(e) => {
+                usage_error(&format!("invalid regex for '{option}': {e}"));
+            }
+        },
+    }
+}
+
+/// Parse a tab stop list like `expand -t`: a single tab width,
+/// or a comma-separated list of strictly increasing columns.
+fn parse_tab_stops(value: &str) -> Vec<usize> {
+    let stops: Option<Vec<usize>> = value.split(',').map(|v| v.trim().parse().ok()).collect();
+    match stops {
+        Some(stops) if stops.iter().all(|&s| s > 0) && stops.windows(2).all(|w| w[0] < w[1]) => {
+            stops
         }
         _ => {
-            eprintln!("nl: invalid numbering style: '{value}'");
-            process::exit(1);
+            usage_error(&format!("invalid tab stops: '{value}'"));
+        }
+    }
+}
+
+fn parse_columns(value: &str) -> Vec<Column> {
+    value
+        .split(',')
+        .map(|name| match name.trim() {
+            "num" => Column::Number,
+            "global" => Column::Global,
+            "percent" => Column::Percent,
+            "offset" => Column::Offset,
+            "section" => Column::Section,
+            "filename" => Column::Filename,
+            "length" => Column::Length,
+            "text" => Column::Text,
+            _ => {
+                usage_error(&format!("invalid column: '{name}'"));
+            }
+        })
+        .collect()
+}
+
+/// Parse `--section-map`: the section selected by a delimiter repeated once,
+/// twice and three times, with `none` for repeats that are plain text.
+fn parse_section_map(value: &str) -> Vec<Option<Section>> {
+    value
+        .split(',')
+        .map(|name| match name.trim() {
+            "header" => Some(Section::Header),
+            "body" => Some(Section::Body),
+            "footer" => Some(Section::Footer),
+            "none" => None,
+            _ => {
+                usage_error(&format!("invalid section: '{name}'"));
+            }
+        })
+        .collect()
+}
+
+/// Parse a byte count with an optional binary `K`, `M` or `G` suffix.
+fn parse_size(value: &str) -> Option<usize> {
+    let (digits, unit) = match value.char_indices().last()? {
+        (i, 'k' | 'K') => (&value[..i], 1 << 10),
+        (i, 'm' | 'M') => (&value[..i], 1 << 20),
+        (i, 'g' | 'G') => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+/// Expand the backslash escapes `\0`, `\n`, `\r`, `\t` and `\\` in an
+/// option value, so separators can contain bytes a shell cannot pass.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('0') => out.push('\0'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn parse_encoding(value: &str) -> Encoding {
+    match Encoding::from_name(value) {
+        Some(enc) => enc,
+        None => {
+            usage_error(&format!("unsupported encoding: '{value}'"));
         }
     }
 }
@@ -87,54 +555,643 @@ fn require_arg<'a>(args: &'a [String], i: &mut usize, option: &str) -> &'a str {
     match args.get(*i) {
         Some(v) => v.as_str(),
         None => {
-            eprintln!("nl: option '{option}' requires an argument");
-            process::exit(1);
+            usage_error(&format!("option '{option}' requires an argument"));
         }
     }
 }
 
+/// Like `require_arg`, but for long options that also accept `--option=VALUE`.
+fn require_long_arg<'a>(
+    args: &'a [String],
+    i: &mut usize,
+    inline: Option<&'a str>,
+    option: &str,
+) -> &'a str {
+    match inline {
+        Some(v) => v,
+        None => require_arg(args, i, option),
+    }
+}
+
+/// Configure numbering to match `cat -n` (style `a`) or `cat -b` (style `t`):
+/// one running counter, no section delimiters, and no padding on unnumbered
+/// lines. Options given after the preset still override it.
+fn apply_cat_preset(config: &mut Config, style: Style) {
+    config.body_style = style;
+    config.number_format = Format::Right;
+    config.number_width = 6;
+    config.separator = "\t".to_string();
+    config.start_number = 1;
+    config.increment = 1;
+    config.join_blank = 1;
+    config.sections = false;
+    config.pad_unnumbered = false;
+}
+
 fn print_usage() {
-    eprintln!("Usage: nl [OPTION]... [FILE]");
+    eprintln!("Usage: nl [OPTION]... [FILE]...");
     eprintln!("Write each FILE to standard output, with line numbers added.");
     eprintln!("With no FILE, or when FILE is -, read standard input.");
     eprintln!();
     eprintln!("Options:");
-    eprintln!("  -b STYLE   body line numbering style (default t)");
+    eprintln!("  -A, --all  number every line of every section, like -ha -ba -fa");
+    eprintln!("  -b, --body-numbering=STYLE  body line numbering style (default t)");
     eprintln!("  -d CC      section delimiter characters (default \\:)");
-    eprintln!("  -f STYLE   footer line numbering style (default n)");
-    eprintln!("  -h STYLE   header line numbering style (default n)");
+    eprintln!("  -f, --footer-numbering=STYLE  footer line numbering style (default n)");
+    eprintln!("  -h, --header-numbering=STYLE  header line numbering style (default n)");
     eprintln!("  -i NUMBER  line number increment (default 1)");
     eprintln!("  -l NUMBER  group of NUMBER empty lines counted as one (default 1)");
-    eprintln!("  -n FORMAT  line number format (ln, rn, rz) (default rn)");
+    eprintln!("      --blank-lines=KIND  which lines style t and -l treat as blank:");
+    eprintln!("                       empty (the default), or whitespace for lines of");
+    eprintln!("                       nothing but spaces, tabs, NBSP and other Unicode");
+    eprintln!("                       whitespace, or invisible to add zero-width");
+    eprintln!("                       spaces, joiners, BOMs and bidirectional marks");
+    eprintln!("      --warn-invisible  warn about lines of only invisible characters");
+    eprintln!("  -n FORMAT  line number format (ln, rn, rz, cn) (default rn)");
     eprintln!("  -p         do not reset line numbers for each section");
+    eprintln!("  -q, --quiet  suppress warnings (they still cause exit status 1)");
+    eprintln!("      --debug  trace section changes, number restarts and why lines");
+    eprintln!("                       are not numbered to stderr");
     eprintln!("  -s STRING  use STRING as separator after number (default TAB)");
     eprintln!("  -v NUMBER  first line number for each section (default 1)");
-    eprintln!("  -w NUMBER  use NUMBER columns for line numbers (default 6)");
+    eprintln!("      --zero-based  start at 0, like -v 0, so each section counts from 0");
+    eprintln!("  -w NUMBER  use NUMBER columns for line numbers (default 6);");
+    eprintln!("             0 prints numbers without padding");
+    eprintln!("  -Z, --null-data-output  end each output record with NUL, not newline");
+    eprintln!("      --content-column=N  pad after the separator so content starts in");
+    eprintln!("                       column N, whatever the widths before it");
+    eprintln!("      --tabstop=N      the terminal has tab stops every N columns (default");
+    eprintln!("                       8); with a tab separator, unnumbered lines are");
+    eprintln!("                       padded to the stop numbered lines' text is on");
+    eprintln!("      --overflow=MODE  numbers wider than -w: grow (the default) pushes");
+    eprintln!("                       the text right; grow-realign widens all lines to");
+    eprintln!("                       match, reading the whole input first");
+    eprintln!("      --progress       show a progress line on stderr while reading");
+    eprintln!("      --binary=POLICY  what to do with binary input: skip, warn, process");
+    eprintln!("                       (default process)");
+    eprintln!("      --encoding=ENC   input encoding: utf-8, latin1, utf-16le, utf-16be,");
+    eprintln!("                       shift_jis (also sjis, cp932, windows-31j), or");
+    eprintln!("                       auto (default utf-8)");
+    eprintln!("      --expand-tabs[=N]  expand tabs in content to spaces, with tab stops");
+    eprintln!("                       every N columns or at a list N1,N2,... (default 8)");
+    eprintln!("      --show-nonprinting  use ^ and M- notation for control characters");
+    eprintln!("                       in content, except TAB, and for invalid UTF-8");
+    eprintln!("      --number-prefix=STRING  print STRING before each line number");
+    eprintln!("      --number-suffix=STRING  print STRING right after each line number,");
+    eprintln!("                       inside its padding");
+    eprintln!("      --show-sign      print a + before positive line numbers");
+    eprintln!("      --base=N         write line numbers in base N, 2 to 36, with digits");
+    eprintln!("                       past 9 as a-z (default 10)");
+    eprintln!("      --number-case=CASE  write digits past 9 in lower (a-z, the");
+    eprintln!("                       default) or upper (A-Z) case");
+    eprintln!("      --digits=SET     write digits 0-9 as arabic (the default),");
+    eprintln!("                       eastern-arabic, persian, devanagari, bengali,");
+    eprintln!("                       gujarati, tamil, thai, khmer, myanmar or");
+    eprintln!("                       fullwidth digits");
+    eprintln!("      --fullwidth-digits  like --digits=fullwidth, two columns each to");
+    eprintln!("                       line up with fullwidth CJK text");
+    eprintln!("      --blank-placeholder=STRING  print STRING in the number column of");
+    eprintln!("                       blank lines that are not numbered, like vim's ~");
+    eprintln!("      --renumber[=REGEX]  strip an existing number matching REGEX from");
+    eprintln!("                       the start of each line and number it afresh");
+    eprintln!("                       (default: nl's own output)");
+    eprintln!("      --strip          remove the numbers nl added with the same -s,");
+    eprintln!("                       --number-prefix and --number-suffix");
+    eprintln!("      --check          report gaps, duplicates and out-of-order numbers");
+    eprintln!("                       in nl output, exiting 1 if there are any");
+    eprintln!("      --total[=FMT]    end with a line counting the numbered lines, as");
+    eprintln!("                       FMT with %n for the count (default");
+    eprintln!("                       \"numbered lines: %n\"); %h, %b and %f count");
+    eprintln!("                       those in headers, bodies and footers");
+    eprintln!("      --file-totals[=FMT]  end with a line per input, as FMT, which also");
+    eprintln!("                       takes %F for its name and %s and %e for its first");
+    eprintln!("                       and last numbers (default \"%F: numbered lines: %n");
+    eprintln!("                       (%s to %e)\")");
+    eprintln!("      --tee=FILE       also write the output to FILE");
+    eprintln!("      --append         add to the end of the --tee FILE, not replace it");
+    eprintln!("      --keep-going     go on to the next input when one cannot be read,");
+    eprintln!("                       exiting nonzero at the end (the default)");
+    eprintln!("      --fail-fast      stop at the first input that cannot be read");
+    eprintln!("      --check-args     check the options and that every input opens,");
+    eprintln!("                       then exit without reading or writing anything");
+    eprintln!("      --report=PATH    write a JSON summary of the run to PATH: counts");
+    eprintln!("                       per input and section, errors and timing");
+    eprintln!("      --errors=FORMAT  report warnings and errors as text (the default)");
+    eprintln!("                       or as JSON objects, one per line");
+    eprintln!("      --pager[=CMD]    page output through CMD (default $NL_PAGER, else");
+    eprintln!("                       less -R) when it is longer than the terminal");
+    eprintln!("      --continue-from-input[=WHICH]  keep lines already numbered as");
+    eprintln!("                       they are, and number the rest on from the first");
+    eprintln!("                       or last (the default) number found in the input");
+    eprintln!("      --count-mode=MODE  number with the line index (lines), or the");
+    eprintln!("                       bytes, chars or words of input before the line");
+    eprintln!("      --global-number  print a running number that sections do not");
+    eprintln!("                       reset before each line's section number");
+    eprintln!("      --section-map=LIST  sections selected by a delimiter repeated 1,");
+    eprintln!("                       2, 3... times: header, body, footer or none");
+    eprintln!("                       (default footer,body,header)");
+    eprintln!("      --section-level=NAME[:STYLE]  add a section kind numbered with");
+    eprintln!("                       STYLE (default t), selected by a delimiter repeated");
+    eprintln!("                       once more than the last --section-map entry or level");
+    eprintln!("      --indent-sections[=N]  choose sections by indentation instead of");
+    eprintln!("                       delimiters: unindented lines are header, indented");
+    eprintln!("                       ones body, and with N columns per level, deeper");
+    eprintln!("                       levels are the --section-levels");
+    eprintln!("      --show-section[=H,B,F]  prefix each line with the label of its");
+    eprintln!("                       section: header, body, footer (default H,B,F)");
+    eprintln!("      --section-label=FMT  replace the empty line printed for a section");
+    eprintln!("                       delimiter with FMT, where %s is the section name");
+    eprintln!("                       and %S the name in capitals");
+    eprintln!("      --restart-per-file  number each file from -v, rather than all");
+    eprintln!("                       files as one stream");
+    eprintln!("      --threads[=N]    with --restart-per-file, number up to N files at");
+    eprintln!("                       once (default: one per CPU), still writing them");
+    eprintln!("                       out in order");
+    eprintln!("      --match-threads[=N]  try p style regexes on lines read ahead");
+    eprintln!("                       with N threads (default: one per CPU), for");
+    eprintln!("                       slow patterns on large input");
+    eprintln!("      --drop-cache     evict each input file from the page cache once");
+    eprintln!("                       it is numbered (Linux)");
+    eprintln!("      --max-memory=SIZE  fail on records longer than SIZE bytes (K, M");
+    eprintln!("                       and G suffixes), and keep at most SIZE of a file's");
+    eprintln!("                       output in memory with --threads, using a temporary");
+    eprintln!("                       file beyond that");
+    eprintln!("      --columns=LIST   output the comma-separated columns in LIST: num,");
+    eprintln!("                       global, offset, section, filename, length, percent,");
+    eprintln!("                       text");
+    eprintln!("      --column-sep=STRING  separator between columns (default TAB)");
+    eprintln!("      --timestamp[=FMT]  prefix each line with the UTC time it was read,");
+    eprintln!("                       formatted with FMT (default %Y-%m-%dT%H:%M:%S%.3fZ),");
+    eprintln!("                       or the seconds since start if FMT is 'elapsed'");
+    eprintln!("      --wrap[=COLS]    wrap content at COLS (default terminal width),");
+    eprintln!("                       indenting continuation rows under the content");
+    eprintln!("      --align-right-margin[=COL]  print content first and the number");
+    eprintln!("                       after it, ending at column COL (default terminal");
+    eprintln!("                       width)");
+    eprintln!("      --max-line-length=N  truncate content to N columns, ending with …");
+    eprintln!("      --report-length  follow truncated content with its original length");
+    eprintln!("      --record-sep=STRING  split input into records ending in STRING");
+    eprintln!("                       instead of lines; an empty STRING separates");
+    eprintln!("                       records by blank lines (\\0 \\n \\r \\t \\\\ allowed)");
+    eprintln!("      --output-record-sep=STRING  end each output record with STRING");
+    eprintln!("                       instead of a newline (same escapes)");
+    eprintln!("      --paragraphs     number only the first line of each paragraph of");
+    eprintln!("                       blank-line separated text, aligning the rest");
+    eprintln!("      --join-continuations  treat lines ending in \\ as continued on the");
+    eprintln!("                       next line, numbering only the first");
+    eprintln!("      --csv            number CSV records rather than lines, so quoted");
+    eprintln!("                       fields spanning lines are not numbered twice");
+    eprintln!("      --log-entries[=REGEX]  number log entries rather than lines: a line");
+    eprintln!("                       continues the previous entry if it is indented or");
+    eprintln!("                       blank, or if given, does not match REGEX");
+    eprintln!("      --between START END  number only lines between one matching the");
+    eprintln!("                       regex START and the next matching END, in any");
+    eprintln!("                       number of such regions");
+    eprintln!("      --normalize=FORM  match the -b/-h/-f, --log-entries and --between");
+    eprintln!("                       regexes against lines in Unicode form nfc or nfd;");
+    eprintln!("                       the lines are written as they were");
+    eprintln!("      --strip-ansi[=MODE]  ignore ANSI color and cursor escapes when");
+    eprintln!("                       checking for blank lines and matching regexes");
+    eprintln!("                       (match, the default), or also remove them from");
+    eprintln!("                       the output (output)");
+    eprintln!("      --markdown[=MODE]  do not number inside ``` or ~~~ fenced code");
+    eprintln!("                       blocks (skip-code, the default), or number only");
+    eprintln!("                       inside them (only-code)");
+    eprintln!("      --min-length=N   number only lines at least N characters long");
+    eprintln!("      --min-length-trim  ignore leading and trailing whitespace when");
+    eprintln!("                       measuring --min-length");
+    eprintln!("      --unique[=annotate]  number a line only the first time its content");
+    eprintln!("                       appears; with annotate, repeats show =N, the");
+    eprintln!("                       number of the first occurrence");
+    eprintln!("      --lang=LANG      do not number blank or comment-only lines of");
+    eprintln!("                       source in LANG (rust, c, py, sh, sql, html, ...)");
+    eprintln!("      --comment-prefix=STR  also treat lines starting with STR as comments");
+    eprintln!("      --diff[=MODE]    number unified diff lines by their line in the");
+    eprintln!("                       new file: context and added lines (all), or");
+    eprintln!("                       added lines only (added)");
+    eprintln!("      --json-array[=MODE]  number the elements of a top-level JSON array,");
+    eprintln!("                       printed one per line (lines) or wrapped in");
+    eprintln!("                       {{\"number\":N,\"value\":...}} objects (annotate)");
+    eprintln!("      --cat-n          number lines exactly like cat -n");
+    eprintln!("      --cat-b          number nonempty lines exactly like cat -b");
+    eprintln!("      --squeeze-blank  suppress repeated empty input lines");
+    eprintln!("      --show-ends      display $ at end of each line of content");
+    eprintln!("      --show-tabs      display TAB characters in content as ^I");
+    eprintln!("      --keep-bom       re-emit a byte order mark found at the start of input");
+    eprintln!("      --output-encoding=ENC  output encoding, one of those of --encoding");
+    eprintln!("                       but auto (default utf-8)");
+    eprintln!("      --crlf           end output lines with CR LF, whatever the input");
+    eprintln!("      --lf             end output lines with LF alone (the default)");
+    eprintln!("      --keep-line-endings  end each output line with the LF, CR LF or");
+    eprintln!("                       nothing its input line ended with");
     eprintln!("      --help display this help and exit");
     eprintln!();
     eprintln!("STYLE is one of:");
-    eprintln!("  a      number all lines");
-    eprintln!("  t      number only nonempty lines");
-    eprintln!("  n      number no lines");
-    eprintln!("  pBRE   number only lines that match the basic regular expression BRE");
+    eprintln!("  a, all        number all lines");
+    eprintln!("  t, nonempty   number only nonempty lines");
+    eprintln!("  n, none       number no lines");
+    eprintln!("  pBRE, regex=BRE  number only lines that match the basic regular");
+    eprintln!("                expression BRE");
+    eprintln!("Styles combine with & (and), | (or) and ! (not), & first: t&pWARN");
+    eprintln!("numbers nonempty lines matching WARN. A p style takes the rest of the");
+    eprintln!("value as its BRE, so it comes last: pFOO|BAR is one BRE.");
     eprintln!();
     eprintln!("Sections are delimited by lines containing only the delimiter");
     eprintln!("characters repeated 1 (footer), 2 (body), or 3 (header) times.");
 }
 
+/// Summary line written by `--total` without a format.
+const DEFAULT_TOTAL: &str = "numbered lines: %n";
+
+/// Line per input written by `--file-totals` without a format.
+const DEFAULT_FILE_TOTALS: &str = "%F: numbered lines: %n (%s to %e)";
+
+/// Pairs of options where one would silently override the other.
+const CONFLICTS: &[(&str, &str)] = &[
+    ("--json-array", "--strip"),
+    ("--json-array", "--check"),
+    ("--json-array", "--continue-from-input"),
+    ("--json-array", "--renumber"),
+    ("--strip", "--check"),
+    ("--strip", "--continue-from-input"),
+    ("--strip", "--renumber"),
+    ("--check", "--continue-from-input"),
+    ("--check", "--renumber"),
+    ("--continue-from-input", "--renumber"),
+    ("--columns", "--align-right-margin"),
+    ("--wrap", "--align-right-margin"),
+    ("--content-column", "--align-right-margin"),
+    ("--indent-sections", "--section-map"),
+    ("--keep-line-endings", "--crlf"),
+    ("--keep-line-endings", "--lf"),
+    ("--keep-line-endings", "--output-record-sep"),
+    ("--keep-line-endings", "--null-data-output"),
+    ("--keep-line-endings", "-Z"),
+    ("--keep-line-endings", "--record-sep"),
+];
+
+/// Options that do nothing without another: numbering files in parallel
+/// needs each to start over, or every file would wait on the one before.
+const REQUIRES: &[(&str, &str)] = &[("--threads", "--restart-per-file")];
+
+/// Reject options given together that cannot both take effect, and
+/// options given without the one they need.
+fn check_conflicts(given: &[&str]) {
+    for (a, b) in CONFLICTS {
+        if given.contains(a) && given.contains(b) {
+            usage_error(&format!("'{a}' cannot be used with '{b}'"));
+        }
+    }
+    for (a, b) in REQUIRES {
+        if given.contains(a) && !given.contains(b) {
+            usage_error(&format!("'{a}' can only be used with '{b}'"));
+        }
+    }
+}
+
+/// Options that read nl's own number fields back, which takes the regex
+/// feature.
+const NEEDS_REGEX: &[&str] = &["--strip", "--check", "--continue-from-input"];
+
+/// Options that change nothing about a line that is neither numbered nor
+/// padded, or whose effect [`copy_through`] carries out itself. Only with
+/// these may [`passes_through`] copy input in blocks; an option missing
+/// here just means the line-by-line path.
+const COPY_SAFE: &[&str] = &[
+    "-b",
+    "-h",
+    "-f",
+    "-n",
+    "-s",
+    "-w",
+    "-v",
+    "-i",
+    "-l",
+    "-p",
+    "-d",
+    "-q",
+    "--quiet",
+    "--body-numbering",
+    "--header-numbering",
+    "--footer-numbering",
+    "--cat-n",
+    "--cat-b",
+    "--zero-based",
+    "--number-prefix",
+    "--number-suffix",
+    "--show-sign",
+    "--base",
+    "--number-case",
+    "--digits",
+    "--fullwidth-digits",
+    "--blank-lines",
+    "--normalize",
+    "--encoding",
+    "--output-encoding",
+    "--keep-bom",
+    "--binary",
+    "--lf",
+    "--errors",
+    "--tee",
+    "--append",
+    "--pager",
+    "--report",
+    "--keep-going",
+    "--fail-fast",
+    "--restart-per-file",
+    "--threads",
+    "--match-threads",
+    "--progress",
+    "--drop-cache",
+];
+
+/// Whether command-line word `arg` is an input or a [`COPY_SAFE`] option,
+/// taking `-bn` and the like as their option.
+fn is_copy_safe(arg: &str) -> bool {
+    let short = ["-b", "-h", "-f", "-n"]
+        .iter()
+        .find(|short| arg.starts_with(**short));
+    !arg.starts_with('-') || arg == "-" || COPY_SAFE.contains(&arg) || short.is_some()
+}
+
+/// Reject options this build of nl cannot carry out.
+fn check_features(given: &[&str]) {
+    if cfg!(feature = "regex") {
+        return;
+    }
+    if let Some(option) = NEEDS_REGEX.iter().find(|option| given.contains(option)) {
+        usage_error(&format!(
+            "'{option}' is not supported: {}",
+            regex::Regex::new("").unwrap_err()
+        ));
+    }
+}
+
+/// Report a bad command line and exit.
+fn usage_error(message: &str) -> ! {
+    diagnostic::report("usage", None, None, message);
+    if !diagnostic::is_json() {
+        eprintln!("Try 'nl --help' for more information.");
+    }
+    process::exit(1);
+}
+
 fn parse_args() -> Config {
     let args: Vec<String> = env::args().skip(1).collect();
     let mut config = Config::default();
+    let mut given = Vec::new();
     let mut i = 0;
 
+    // Options before --errors=json can be wrong too
+    if args.iter().any(|arg| arg == "--errors=json") {
+        diagnostic::use_json();
+    }
+
     while i < args.len() {
-        let arg = args[i].as_str();
+        // Long options may carry their value inline: --option=VALUE
+        let (arg, inline) = match args[i].split_once('=') {
+            Some((name, val)) if name.starts_with("--") => (name, Some(val)),
+            _ => (args[i].as_str(), None),
+        };
+        given.push(arg);
         match arg {
             "--help" => {
                 print_usage();
                 process::exit(0);
             }
             "-p" => config.no_renumber = true,
+            "-A" | "--all" => {
+                config.header_style = Style::All;
+                config.body_style = Style::All;
+                config.footer_style = Style::All;
+            }
+            "-q" | "--quiet" => config.quiet = true,
+            "--debug" => config.debug = true,
+            "--progress" => config.progress = true,
+            "--keep-bom" => config.keep_bom = true,
+            "--show-nonprinting" => config.show_nonprinting = true,
+            "--show-ends" => config.show_ends = true,
+            "--show-tabs" => config.show_tabs = true,
+            "--squeeze-blank" => config.squeeze_blank = true,
+            "--count-mode" => {
+                let val = require_long_arg(&args, &mut i, inline, "--count-mode");
+                config.count_mode = match val {
+                    "lines" => CountMode::Lines,
+                    "bytes" => CountMode::Bytes,
+                    "chars" => CountMode::Chars,
+                    "words" => CountMode::Words,
+                    _ => {
+                        usage_error(&format!("invalid count mode: '{val}'"));
+                    }
+                };
+            }
+            "--columns" => {
+                let val = require_long_arg(&args, &mut i, inline, "--columns");
+                config.columns = Some(parse_columns(val));
+            }
+            "--column-sep" => {
+                let val = require_long_arg(&args, &mut i, inline, "--column-sep");
+                config.column_separator = val.to_string();
+            }
+            "--timestamp" => {
+                config.timestamp = Some(inline.unwrap_or(timestamp::DEFAULT_FORMAT).to_string());
+            }
+            "--wrap" => {
+                config.wrap = Some(match inline {
+                    None => terminal::width(),
+                    Some(val) => match val.parse() {
+                        Ok(w) if w > 0 => w,
+                        _ => {
+                            usage_error(&format!("invalid wrap width: '{val}'"));
+                        }
+                    },
+                });
+            }
+            "--align-right-margin" => {
+                config.right_margin = Some(match inline {
+                    None => terminal::width(),
+                    Some(val) => match val.parse() {
+                        Ok(w) if w > 0 => w,
+                        _ => {
+                            usage_error(&format!("invalid right margin: '{val}'"));
+                        }
+                    },
+                });
+            }
+            "--max-line-length" => {
+                let val = require_long_arg(&args, &mut i, inline, "--max-line-length");
+                config.max_line_length = match val.parse() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => {
+                        usage_error(&format!("invalid maximum line length: '{val}'"));
+                    }
+                };
+            }
+            "--report-length" => config.report_length = true,
+            "--record-sep" => {
+                let val = require_long_arg(&args, &mut i, inline, "--record-sep");
+                config.record_separator = match unescape(val).as_str() {
+                    "" => RecordSeparator::Paragraph,
+                    "\n" => RecordSeparator::Newline,
+                    sep => RecordSeparator::Delimiter(sep.as_bytes().to_vec()),
+                };
+            }
+            "-Z" | "--null-data-output" => config.line_end = "\0".to_string(),
+            "--output-record-sep" => {
+                let val = require_long_arg(&args, &mut i, inline, "--output-record-sep");
+                config.line_end = unescape(val);
+            }
+            "--paragraphs" => config.paragraphs = true,
+            "--join-continuations" => config.join_continuations = true,
+            "--csv" => config.csv = true,
+            "--log-entries" => {
+                config.log_entries = Some(match inline {
+                    None => EntryStart::Unindented,
+                    Some(pattern) => match Regex::new(pattern) {
+                        Ok(re) => EntryStart::Matching(re),
+                        Err(e) => {
+                            usage_error(&format!("invalid regex for '--log-entries': {e}"));
+                        }
+                    },
+                });
+            }
+            "--between" => {
+                let start = require_long_arg(&args, &mut i, inline, "--between");
+                let end = require_arg(&args, &mut i, "--between");
+                let compile = |pattern| match Regex::new(pattern) {
+                    Ok(re) => re,
+                    Err(e) => usage_error(&format!("invalid regex for '--between': {e}")),
+                };
+                config.between = Some((compile(start), compile(end)));
+            }
+            "--normalize" => {
+                let val = require_long_arg(&args, &mut i, inline, "--normalize");
+                config.normalize = match val {
+                    "nfc" => Some(normalize::Form::Nfc),
+                    "nfd" => Some(normalize::Form::Nfd),
+                    _ => {
+                        usage_error(&format!("invalid normalization form: '{val}'"));
+                    }
+                };
+            }
+            "--strip-ansi" => {
+                config.strip_ansi = match inline.unwrap_or("match") {
+                    "match" => Some(StripAnsi::Match),
+                    "output" => Some(StripAnsi::Output),
+                    val => {
+                        usage_error(&format!("invalid strip-ansi mode: '{val}'"));
+                    }
+                };
+            }
+            "--markdown" => {
+                config.markdown = match inline.unwrap_or("skip-code") {
+                    "skip-code" => Some(MarkdownMode::SkipCode),
+                    "only-code" => Some(MarkdownMode::OnlyCode),
+                    val => {
+                        usage_error(&format!("invalid markdown mode: '{val}'"));
+                    }
+                };
+            }
+            "--min-length" => {
+                let val = require_long_arg(&args, &mut i, inline, "--min-length");
+                config.min_length = match val.parse() {
+                    Ok(n) => Some(n),
+                    _ => {
+                        usage_error(&format!("invalid minimum length: '{val}'"));
+                    }
+                };
+            }
+            "--min-length-trim" => config.min_length_trim = true,
+            "--unique" => {
+                config.unique = match inline {
+                    None => Some(UniqueMode::Plain),
+                    Some("annotate") => Some(UniqueMode::Annotate),
+                    Some(val) => {
+                        usage_error(&format!("invalid unique mode: '{val}'"));
+                    }
+                };
+            }
+            "--lang" => {
+                let val = require_long_arg(&args, &mut i, inline, "--lang");
+                let Some(syntax) = CommentSyntax::for_language(val) else {
+                    usage_error(&format!("unknown language: '{val}'"));
+                };
+                // Prefixes given before --lang still count
+                let comments = config.comments.get_or_insert_with(CommentSyntax::default);
+                comments.line.extend(syntax.line);
+                comments.block.extend(syntax.block);
+            }
+            "--comment-prefix" => {
+                let val = require_long_arg(&args, &mut i, inline, "--comment-prefix");
+                // Every line starts with the empty string
+                if val.is_empty() {
+                    usage_error("empty comment prefix");
+                }
+                config
+                    .comments
+                    .get_or_insert_with(CommentSyntax::default)
+                    .line
+                    .push(val.to_string());
+            }
+            "--diff" => {
+                config.diff = match inline.unwrap_or("all") {
+                    "all" => Some(DiffMode::All),
+                    "added" => Some(DiffMode::Added),
+                    val => {
+                        usage_error(&format!("invalid diff mode: '{val}'"));
+                    }
+                };
+            }
+            "--json-array" => {
+                config.json_array = match inline.unwrap_or("lines") {
+                    "lines" => Some(JsonOutput::Lines),
+                    "annotate" => Some(JsonOutput::Annotate),
+                    val => {
+                        usage_error(&format!("invalid JSON output mode: '{val}'"));
+                    }
+                };
+            }
+            "--cat-n" => apply_cat_preset(&mut config, Style::All),
+            "--cat-b" => apply_cat_preset(&mut config, Style::NonEmpty),
+            "--expand-tabs" => {
+                config.tab_stops = Some(parse_tab_stops(inline.unwrap_or("8")));
+            }
+            "--encoding" => {
+                let val = require_long_arg(&args, &mut i, inline, "--encoding");
+                config.input_encoding = parse_encoding(val);
+            }
+            "--output-encoding" => {
+                let val = require_long_arg(&args, &mut i, inline, "--output-encoding");
+                config.output_encoding = match parse_encoding(val) {
+                    Encoding::Auto => {
+                        usage_error("output encoding cannot be 'auto'");
+                    }
+                    enc => enc,
+                };
+            }
+            "--blank-lines" => {
+                let val = require_long_arg(&args, &mut i, inline, "--blank-lines");
+                config.blank_lines = match val {
+                    "empty" => BlankLines::Empty,
+                    "whitespace" => BlankLines::Whitespace,
+                    "invisible" => BlankLines::Invisible,
+                    _ => {
+                        usage_error(&format!("invalid blank line kind: '{val}'"));
+                    }
+                };
+            }
+            "--warn-invisible" => config.warn_invisible = true,
+            "--binary" => {
+                let val = require_long_arg(&args, &mut i, inline, "--binary");
+                config.binary_policy = match val {
+                    "skip" => BinaryPolicy::Skip,
+                    "warn" => BinaryPolicy::Warn,
+                    "process" => BinaryPolicy::Process,
+                    _ => {
+                        usage_error(&format!("invalid binary policy: '{val}'"));
+                    }
+                };
+            }
             "-b" => {
                 let val = require_arg(&args, &mut i, "-b");
                 config.body_style = parse_style(val, "-b");
@@ -147,6 +1204,18 @@ fn parse_args() -> Config {
                 let val = require_arg(&args, &mut i, "-h");
                 config.header_style = parse_style(val, "-h");
             }
+            "--body-numbering" => {
+                let val = require_long_arg(&args, &mut i, inline, "--body-numbering");
+                config.body_style = parse_style(val, "--body-numbering");
+            }
+            "--footer-numbering" => {
+                let val = require_long_arg(&args, &mut i, inline, "--footer-numbering");
+                config.footer_style = parse_style(val, "--footer-numbering");
+            }
+            "--header-numbering" => {
+                let val = require_long_arg(&args, &mut i, inline, "--header-numbering");
+                config.header_style = parse_style(val, "--header-numbering");
+            }
             "-d" => {
                 let val = require_arg(&args, &mut i, "-d");
                 let chars: Vec<char> = val.chars().collect();
@@ -154,20 +1223,204 @@ fn parse_args() -> Config {
                     1 => config.section_delimiter = [chars[0], ':'],
                     2 => config.section_delimiter = [chars[0], chars[1]],
                     _ => {
-                        eprintln!("nl: invalid section delimiter: '{val}'");
-                        process::exit(1);
+                        usage_error(&format!("invalid section delimiter: '{val}'"));
                     }
                 }
             }
+            "--indent-sections" => {
+                config.indent_sections = Some(match inline {
+                    None => 0,
+                    Some(val) => match val.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => {
+                            usage_error(&format!("invalid indent width: '{val}'"));
+                        }
+                    },
+                });
+            }
+            "--section-level" => {
+                let val = require_long_arg(&args, &mut i, inline, "--section-level");
+                let (name, style) = val.split_once(':').unwrap_or((val, "t"));
+                config.section_levels.push(SectionLevel {
+                    name: name.to_string(),
+                    style: parse_style(style, "--section-level"),
+                });
+            }
+            "--section-map" => {
+                let val = require_long_arg(&args, &mut i, inline, "--section-map");
+                config.section_map = parse_section_map(val);
+            }
             "-n" => {
                 let val = require_arg(&args, &mut i, "-n");
                 config.number_format = match val {
-                    "ln" => NumberFormat::Left,
-                    "rn" => NumberFormat::Right,
-                    "rz" => NumberFormat::RightZero,
+                    "ln" => Format::Left,
+                    "rn" => Format::Right,
+                    "rz" => Format::RightZero,
+                    "cn" => Format::Center,
+                    _ => {
+                        usage_error(&format!("invalid line number format: '{val}'"));
+                    }
+                };
+            }
+            "--number-prefix" => {
+                let val = require_long_arg(&args, &mut i, inline, "--number-prefix");
+                config.number_prefix = val.to_string();
+            }
+            "--number-suffix" => {
+                let val = require_long_arg(&args, &mut i, inline, "--number-suffix");
+                config.number_suffix = val.to_string();
+            }
+            "--show-sign" => config.show_sign = true,
+            "--drop-cache" => config.drop_cache = true,
+            "--max-memory" => {
+                let val = require_long_arg(&args, &mut i, inline, "--max-memory");
+                config.max_memory = match parse_size(val) {
+                    Some(n) if n > 0 => Some(n),
+                    _ => {
+                        usage_error(&format!("invalid memory size: '{val}'"));
+                    }
+                };
+            }
+            "--overflow" => {
+                config.overflow = match require_long_arg(&args, &mut i, inline, "--overflow") {
+                    "grow" => Overflow::Grow,
+                    "grow-realign" => Overflow::GrowRealign,
+                    val => usage_error(&format!("invalid overflow mode: '{val}'")),
+                };
+            }
+            "--content-column" => {
+                let val = require_long_arg(&args, &mut i, inline, "--content-column");
+                config.content_column = match val.parse() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => usage_error(&format!("invalid content column: '{val}'")),
+                };
+            }
+            "--tabstop" => {
+                let val = require_long_arg(&args, &mut i, inline, "--tabstop");
+                config.tabstop = match val.parse() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => usage_error(&format!("invalid tab stop: '{val}'")),
+                };
+            }
+            "--crlf" => config.line_end = "\r\n".to_string(),
+            "--lf" => config.line_end = "\n".to_string(),
+            "--keep-line-endings" => config.keep_line_endings = true,
+            "--zero-based" => config.start_number = 0,
+            "--base" => {
+                let val = require_long_arg(&args, &mut i, inline, "--base");
+                config.base = match val.parse() {
+                    Ok(n) if (2..=36).contains(&n) => n,
+                    _ => usage_error(&format!("invalid base: '{val}'")),
+                };
+            }
+            "--number-case" => {
+                let val = require_long_arg(&args, &mut i, inline, "--number-case");
+                config.number_case = match val {
+                    "lower" => NumberCase::Lower,
+                    "upper" => NumberCase::Upper,
                     _ => {
-                        eprintln!("nl: invalid line number format: '{val}'");
-                        process::exit(1);
+                        usage_error(&format!("invalid number case: '{val}'"));
+                    }
+                };
+            }
+            "--digits" => {
+                let val = require_long_arg(&args, &mut i, inline, "--digits");
+                config.zero_digit = match DIGIT_SETS.iter().find(|(name, _)| *name == val) {
+                    Some(&(_, zero)) => zero,
+                    None => {
+                        usage_error(&format!("invalid digit set: '{val}'"));
+                    }
+                };
+            }
+            "--fullwidth-digits" => config.zero_digit = '\u{FF10}',
+            "--restart-per-file" => config.restart_per_file = true,
+            "--threads" => {
+                config.threads = match inline {
+                    None => thread::available_parallelism().map_or(1, |n| n.get()),
+                    Some(val) => match val.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => {
+                            usage_error(&format!("invalid number of threads: '{val}'"));
+                        }
+                    },
+                };
+            }
+            "--match-threads" => {
+                config.match_threads = match inline {
+                    None => thread::available_parallelism().map_or(1, |n| n.get()),
+                    Some(val) => match val.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => {
+                            usage_error(&format!("invalid number of threads: '{val}'"));
+                        }
+                    },
+                };
+            }
+            "--blank-placeholder" => {
+                let val = require_long_arg(&args, &mut i, inline, "--blank-placeholder");
+                config.blank_placeholder = Some(val.to_string());
+            }
+            "--global-number" => config.global_number = true,
+            "--section-label" => {
+                let val = require_long_arg(&args, &mut i, inline, "--section-label");
+                config.section_banner = Some(unescape(val));
+            }
+            "--show-section" => {
+                let val = inline.unwrap_or("H,B,F");
+                let labels: Vec<String> = val.split(',').map(unescape).collect();
+                config.section_labels = match <[String; 3]>::try_from(labels) {
+                    Ok(labels) => Some(labels),
+                    Err(_) => {
+                        usage_error(&format!("invalid section labels: '{val}'"));
+                    }
+                };
+            }
+            "--strip" => config.strip = true,
+            "--check" => config.check = true,
+            "--file-totals" => {
+                config.file_totals = Some(unescape(inline.unwrap_or(DEFAULT_FILE_TOTALS)));
+            }
+            "--tee" => {
+                let val = require_long_arg(&args, &mut i, inline, "--tee");
+                config.tee = Some(val.to_string());
+            }
+            "--append" => config.append = true,
+            "--keep-going" => config.fail_fast = false,
+            "--fail-fast" => config.fail_fast = true,
+            "--check-args" => config.check_args = true,
+            "--report" => {
+                let val = require_long_arg(&args, &mut i, inline, "--report");
+                config.report = Some(val.to_string());
+            }
+            "--total" => config.total = Some(unescape(inline.unwrap_or(DEFAULT_TOTAL))),
+            "--errors" => match require_long_arg(&args, &mut i, inline, "--errors") {
+                "text" => {}
+                "json" => diagnostic::use_json(),
+                val => usage_error(&format!("invalid error format: '{val}'")),
+            },
+            "--pager" => {
+                config.pager = Some(match inline {
+                    Some(val) => val.to_string(),
+                    None => {
+                        env::var("NL_PAGER").unwrap_or_else(|_| pager::DEFAULT_COMMAND.to_string())
+                    }
+                });
+            }
+            "--continue-from-input" => {
+                config.continue_from = match inline.unwrap_or("last") {
+                    "first" => Some(ContinueFrom::First),
+                    "last" => Some(ContinueFrom::Last),
+                    val => {
+                        usage_error(&format!("invalid --continue-from-input: '{val}'"));
+                    }
+                };
+            }
+            "--renumber" => {
+                let pattern = inline.unwrap_or(RENUMBER_PATTERN);
+                config.renumber = match Regex::new(pattern) {
+                    Ok(re) => Some(re),
+                    Err(e) => {
+                        usage_error(&format!("invalid regex for '--renumber': {e}"));
                     }
                 };
             }
@@ -177,11 +1430,11 @@ fn parse_args() -> Config {
             }
             "-w" => {
                 let val = require_arg(&args, &mut i, "-w");
+                // 0 means no padding at all, for compact machine-readable output
                 config.number_width = match val.parse() {
-                    Ok(w) if w > 0 => w,
+                    Ok(w) => w,
                     _ => {
-                        eprintln!("nl: invalid line number field width: '{val}'");
-                        process::exit(1);
+                        usage_error(&format!("invalid line number field width: '{val}'"));
                     }
                 };
             }
@@ -190,8 +1443,7 @@ fn parse_args() -> Config {
                 config.start_number = match val.parse() {
                     Ok(v) => v,
                     _ => {
-                        eprintln!("nl: invalid starting line number: '{val}'");
-                        process::exit(1);
+                        usage_error(&format!("invalid starting line number: '{val}'"));
                     }
                 };
             }
@@ -200,8 +1452,7 @@ fn parse_args() -> Config {
                 config.increment = match val.parse() {
                     Ok(inc) => inc,
                     _ => {
-                        eprintln!("nl: invalid line number increment: '{val}'");
-                        process::exit(1);
+                        usage_error(&format!("invalid line number increment: '{val}'"));
                     }
                 };
             }
@@ -210,8 +1461,7 @@ fn parse_args() -> Config {
                 config.join_blank = match val.parse() {
                     Ok(l) if l > 0 => l,
                     _ => {
-                        eprintln!("nl: invalid line number of blank lines: '{val}'");
-                        process::exit(1);
+                        usage_error(&format!("invalid line number of blank lines: '{val}'"));
                     }
                 };
             }
@@ -227,109 +1477,2083 @@ fn parse_args() -> Config {
             }
             s if s.starts_with("-n") && s.len() > 2 => {
                 config.number_format = match &s[2..] {
-                    "ln" => NumberFormat::Left,
-                    "rn" => NumberFormat::Right,
-                    "rz" => NumberFormat::RightZero,
+                    "ln" => Format::Left,
+                    "rn" => Format::Right,
+                    "rz" => Format::RightZero,
+                    "cn" => Format::Center,
                     v => {
-                        eprintln!("nl: invalid line number format: '{v}'");
-                        process::exit(1);
+                        usage_error(&format!("invalid line number format: '{v}'"));
                     }
                 };
             }
-            s if !s.starts_with('-') || s == "-" => {
-                config.file = if s == "-" { None } else { Some(s.to_string()) };
-            }
+            s if !s.starts_with('-') || s == "-" => config.files.push(s.to_string()),
             _ => {
-                eprintln!("nl: invalid option '{arg}'");
-                eprintln!("Try 'nl --help' for more information.");
-                process::exit(1);
+                usage_error(&format!("invalid option '{arg}'"));
             }
         }
         i += 1;
     }
+    check_conflicts(&given);
+    check_features(&given);
+    config.copy_safe = given.iter().all(|arg| is_copy_safe(arg));
 
     config
 }
 
-fn format_number(num: i64, width: usize, format: NumberFormat) -> String {
-    match format {
-        NumberFormat::Left => format!("{:<width$}", num),
-        NumberFormat::Right => format!("{:>width$}", num),
-        NumberFormat::RightZero => format!("{:>0width$}", num),
+/// The name of `section`; a `Section::Level` is a `--section-level`, by
+/// position among them.
+fn section_name(section: Section, config: &Config) -> &str {
+    match section {
+        Section::Level(i) => &config.section_levels[i].name,
+        _ => section.name(),
     }
 }
 
-fn should_number(line: &str, style: &NumberStyle) -> bool {
-    match style {
-        NumberStyle::All => true,
-        NumberStyle::NonEmpty => !line.is_empty(),
-        NumberStyle::None => false,
-        NumberStyle::Pattern(re) => re.is_match(line),
+/// The section of a line indented `depth` levels with `--indent-sections`.
+/// Levels past the last `--section-level` share it.
+fn section_for_depth(depth: usize, config: &Config) -> Section {
+    match depth {
+        0 => Section::Header,
+        1 => Section::Body,
+        _ if config.section_levels.is_empty() => Section::Body,
+        _ => Section::Level((depth - 2).min(config.section_levels.len() - 1)),
     }
 }
 
-/// Build the section delimiter strings from the two-character delimiter.
-/// Returns (header_delim, body_delim, footer_delim).
-fn section_delimiters(delim: [char; 2]) -> (String, String, String) {
-    let pair: String = delim.iter().collect();
-    let header = format!("{pair}{pair}{pair}");
-    let body = format!("{pair}{pair}");
-    let footer = pair;
-    (header, body, footer)
+/// The section a delimiter repeated `n` times selects, if any: from
+/// `--section-map`, then the `--section-level`s in order.
+fn section_for_repeats(n: usize, config: &Config) -> Option<Section> {
+    let map = &config.section_map;
+    match map.get(n - 1) {
+        Some(section) => *section,
+        None => {
+            let level = n - 1 - map.len();
+            (level < config.section_levels.len()).then_some(Section::Level(level))
+        }
+    }
 }
 
-fn number_lines(reader: impl Read, config: &Config) -> io::Result<()> {
-    let buf = BufReader::new(reader);
-    let mut line_number = config.start_number;
-    let mut out = io::BufWriter::new(io::stdout().lock());
-
-    let mut current_section = Section::Body;
-    let mut blank_count: usize = 0;
-
-    let (header_delim, body_delim, footer_delim) = section_delimiters(config.section_delimiter);
-
-    for line in buf.lines() {
-        let line = line?;
-
-        // Check for section delimiter (must check longest first)
-        if line == header_delim {
-            current_section = Section::Header;
-            if !config.no_renumber {
-                line_number = config.start_number;
-            }
-            blank_count = 0;
-            writeln!(out)?;
+/// The line printed for a section delimiter with `--section-label`, with
+/// `%s`, `%S` and `%%` expanded.
+fn section_banner(format: &str, name: &str) -> String {
+    let mut out = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
             continue;
         }
-        if line == body_delim {
-            current_section = Section::Body;
-            if !config.no_renumber {
-                line_number = config.start_number;
+        match chars.next() {
+            Some('s') => out.push_str(name),
+            Some('S') => out.push_str(&name.to_uppercase()),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
             }
-            blank_count = 0;
-            writeln!(out)?;
-            continue;
+            None => out.push('%'),
         }
-        if line == footer_delim {
-            current_section = Section::Footer;
-            if !config.no_renumber {
-                line_number = config.start_number;
-            }
+    }
+    out
+}
+
+/// Write the `--file-totals` and `--total` lines for the inputs that were
+/// numbered: each path with its tally.
+fn write_summary(
+    out: &mut impl Write,
+    numbered: &[(&String, Tally)],
+    config: &Config,
+) -> io::Result<()> {
+    let mut out = EncodingWriter::new(out, config.output_encoding);
+    let mut total = Tally::default();
+    for (path, tally) in numbered {
+        if let Some(format) = &config.file_totals {
+            let name = if *path == "-" { "standard input" } else { path };
+            write!(out, "{}", summary_line(format, name, tally))?;
+            end_line(&mut out, config)?;
+        }
+        total.add(*tally);
+    }
+    if let Some(format) = &config.total {
+        write!(out, "{}", summary_line(format, "total", &total))?;
+        end_line(&mut out, config)?;
+    }
+    Ok(())
+}
+
+/// Expand a `--total` or `--file-totals` format for input `name`: `%F`
+/// becomes the name, `%n` the count of numbered lines, `%h`, `%b` and `%f`
+/// the counts in header, body and footer sections, and `%s` and `%e` the
+/// first and last numbers given (`-` if none).
+fn summary_line(format: &str, name: &str, total: &Tally) -> String {
+    let mut out = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('F') => out.push_str(name),
+            Some('s') => out.push_str(&total.first.map_or("-".to_string(), |n| n.to_string())),
+            Some('e') => out.push_str(&total.last.map_or("-".to_string(), |n| n.to_string())),
+            Some('n') => out.push_str(&total.numbered.to_string()),
+            Some('h') => out.push_str(&total.header.to_string()),
+            Some('b') => out.push_str(&total.body.to_string()),
+            Some('f') => out.push_str(&total.footer.to_string()),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// The `--show-section` label for `section` and a space, padded so all
+/// labels take the same width. `--section-level`s are labeled by name.
+fn section_label(section: Section, config: &Config) -> Option<String> {
+    let labels = config.section_labels.as_ref()?;
+    let names = config.section_levels.iter().map(|level| &level.name);
+    let width = labels
+        .iter()
+        .chain(names)
+        .map(|l| width::str_width(l))
+        .max()
+        .unwrap_or(0);
+    let label = match section {
+        Section::Header => &labels[0],
+        Section::Body => &labels[1],
+        Section::Footer => &labels[2],
+        Section::Level(i) => &config.section_levels[i].name,
+    };
+    let padding = width - width::str_width(label);
+    Some(format!("{label}{} ", " ".repeat(padding)))
+}
+
+/// The digit sets `--digits` knows, by name and the character for 0.
+const DIGIT_SETS: &[(&str, char)] = &[
+    ("arabic", '0'),
+    ("eastern-arabic", '\u{660}'),
+    ("persian", '\u{6F0}'),
+    ("devanagari", '\u{966}'),
+    ("bengali", '\u{9E6}'),
+    ("gujarati", '\u{AE6}'),
+    ("tamil", '\u{BE6}'),
+    ("thai", '\u{E50}'),
+    ("khmer", '\u{17E0}'),
+    ("myanmar", '\u{1040}'),
+    ("fullwidth", '\u{FF10}'),
+];
+
+/// What `--renumber` strips by default: a number and tab as nl writes them,
+/// or the padding nl writes for an unnumbered empty line.
+const RENUMBER_PATTERN: &str = r"^(?: *[+-]?\d+\t| +$)";
+
+/// The number field of an output line: `--number-prefix`, the formatted
+/// number and `--number-suffix`, or blank padding of the same width for
+/// unnumbered lines. The suffix sticks to the number, inside the padding.
+fn gutter(number: Option<i64>, config: &Config) -> String {
+    let suffix = &config.number_suffix;
+    let width = config.number_width + width::str_width(suffix);
+    match number {
+        // The sign goes before the zeros: +0042
+        Some(n) if config.number_format == Format::RightZero => {
+            let sign = number_sign(n, config);
+            let digits = number_digits(n, config);
+            let zero = config.zero_digit;
+            let room = config
+                .number_width
+                .saturating_sub(sign.len() + width::str_width(&digits));
+            // A column too few for another wide zero is left as a space
+            let zero_width = width::char_width(zero).max(1);
+            let space = " ".repeat(room % zero_width);
+            let zeros = zero.to_string().repeat(room / zero_width);
+            format!(
+                "{}{space}{sign}{zeros}{digits}{suffix}",
+                config.number_prefix
+            )
+        }
+        Some(n) => {
+            let sign = number_sign(n, config);
+            let digits = number_digits(n, config);
+            config.number_prefix.clone() + &pad(&format!("{sign}{digits}{suffix}"), width, config)
+        }
+        None => " ".repeat(width::str_width(&config.number_prefix) + width),
+    }
+}
+
+/// Pad `text` to `width` display columns, on the side given by the number
+/// format.
+fn pad(text: &str, width: usize, config: &Config) -> String {
+    let padding = width.saturating_sub(width::str_width(text));
+    let left = match config.number_format {
+        Format::Left => 0,
+        Format::Right | Format::RightZero => padding,
+        Format::Center => padding / 2,
+    };
+    format!("{}{text}{}", " ".repeat(left), " ".repeat(padding - left))
+}
+
+/// Position within a unified diff hunk, for `--diff` numbering.
+struct Hunk {
+    next_new_line: i64,
+    old_remaining: i64,
+    new_remaining: i64,
+}
+
+impl Hunk {
+    /// Parse a hunk header like `@@ -12,5 +14,7 @@`.
+    fn parse(line: &str) -> Option<Hunk> {
+        let range = line.strip_prefix("@@ -")?.split(" @@").next()?;
+        let (old, new) = range.split_once(" +")?;
+        let count = |spec: &str| -> Option<(i64, i64)> {
+            match spec.split_once(',') {
+                Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+                None => Some((spec.parse().ok()?, 1)),
+            }
+        };
+        let (_, old_len) = count(old)?;
+        let (new_start, new_len) = count(new)?;
+        Some(Hunk {
+            next_new_line: new_start,
+            old_remaining: old_len,
+            new_remaining: new_len,
+        })
+    }
+
+    /// Account for one line of the hunk body. Returns the new-file line
+    /// number of context and added lines.
+    fn advance(&mut self, line: &str) -> Option<i64> {
+        let in_new = match line.as_bytes().first() {
+            Some(b'+') => {
+                self.new_remaining -= 1;
+                true
+            }
+            Some(b'-') => {
+                self.old_remaining -= 1;
+                false
+            }
+            // "\ No newline at end of file"
+            Some(b'\\') => false,
+            // Context; some tools strip the space from empty context lines
+            _ => {
+                self.old_remaining -= 1;
+                self.new_remaining -= 1;
+                true
+            }
+        };
+        if !in_new {
+            return None;
+        }
+        self.next_new_line += 1;
+        Some(self.next_new_line - 1)
+    }
+
+    fn is_done(&self) -> bool {
+        self.old_remaining <= 0 && self.new_remaining <= 0
+    }
+}
+
+/// If `line` opens a Markdown code fence (three or more backticks or tildes,
+/// indented at most three spaces), return the fence character and length.
+fn fence_marker(line: &str) -> Option<(char, usize)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let c = rest.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let len = rest.len() - rest.trim_start_matches(c).len();
+    // A backtick fence's info string may not contain backticks
+    let info = &rest[len..];
+    (len >= 3 && !(c == '`' && info.contains('`'))).then_some((c, len))
+}
+
+/// Whether `line` closes the code fence `open`: the same character, at least
+/// as long, with nothing but whitespace after it.
+fn closes_fence(line: &str, open: (char, usize)) -> bool {
+    fence_marker(line).is_some_and(|(c, len)| {
+        let info = line.trim_start_matches(' ').trim_start_matches(c);
+        c == open.0 && len >= open.1 && info.trim().is_empty()
+    })
+}
+
+/// The number field of a `--unique=annotate` duplicate: `=N`, where N is the
+/// number of the line's first occurrence, aligned like a number.
+fn duplicate_gutter(original: i64, config: &Config) -> String {
+    let suffix = &config.number_suffix;
+    let width = config.number_width + width::str_width(suffix);
+    config.number_prefix.clone() + &pad(&format!("={original}{suffix}"), width, config)
+}
+
+/// The separator with everything but tabs blanked out.
+fn blank_separator(config: &Config) -> String {
+    config
+        .separator
+        .chars()
+        .map(|c| match c {
+            '\t' => "\t".to_string(),
+            _ => " ".repeat(width::char_width(c)),
+        })
+        .collect()
+}
+
+/// With `--global-number`, put the running number `global` and a separator
+/// (both blank when `None`) in front of the section's number `field`.
+fn with_global(field: String, global: Option<i64>, config: &Config) -> String {
+    if !config.global_number {
+        return field;
+    }
+    let separator = match global {
+        Some(_) => config.separator.clone(),
+        None => blank_separator(config),
+    };
+    gutter(global, config) + &separator + &field
+}
+
+/// The number field of an unnumbered line: blank, or for an empty line the
+/// `--blank-placeholder`, aligned like a number.
+fn unnumbered_gutter(line: &str, config: &Config) -> String {
+    match &config.blank_placeholder {
+        Some(placeholder) if line.is_empty() => {
+            let width = config.number_width + width::str_width(&config.number_suffix);
+            " ".repeat(width::str_width(&config.number_prefix)) + &pad(placeholder, width, config)
+        }
+        _ => gutter(None, config),
+    }
+}
+
+/// Blank stand-in for the number and separator of a numbered line, so that
+/// continuation lines start their content in the same column.
+fn continuation_leader(config: &Config) -> String {
+    with_global(gutter(None, config), None, config) + &blank_separator(config)
+}
+
+/// Tab stop interval assumed for the terminal when measuring output,
+/// unless `--tabstop` says otherwise.
+const TERMINAL_TAB_WIDTH: usize = 8;
+
+/// The terminal's tab stop interval.
+fn tab_width(config: &Config) -> usize {
+    config.tabstop.unwrap_or(TERMINAL_TAB_WIDTH)
+}
+
+/// The display column reached after writing `c` at `column`, with tab
+/// stops every `tab` columns.
+fn advance(column: usize, c: char, tab: usize) -> usize {
+    if c == '\t' {
+        (column / tab + 1) * tab
+    } else {
+        column + width::char_width(c)
+    }
+}
+
+/// The display column reached after writing `s` at `column`, passing over
+/// ANSI escape sequences, which a terminal does not show.
+fn advance_str(column: usize, s: &str, tab: usize) -> usize {
+    ansi::tokens(s).fold(column, |column, (_, token)| match token {
+        ansi::Token::Char(c) => advance(column, c, tab),
+        ansi::Token::Escape(_) => column,
+    })
+}
+
+/// Write `leader` and `content` as one output line. With `--wrap`, content
+/// past the wrap width continues on further rows, indented to where the
+/// content started. `column` is the display column `leader` starts at, and
+/// `ending` the record's terminator.
+fn write_row(
+    out: &mut impl Write,
+    column: usize,
+    leader: &str,
+    content: &str,
+    ending: &str,
+    config: &Config,
+) -> io::Result<()> {
+    write!(out, "{leader}")?;
+    let tab = tab_width(config);
+    let mut indent = advance_str(column, leader, tab);
+    // Columns are counted from 1
+    if let Some(start) = config.content_column
+        && !leader.is_empty()
+        && !content.is_empty()
+        && indent < start - 1
+    {
+        write!(out, "{}", " ".repeat(start - 1 - indent))?;
+        indent = start - 1;
+    }
+    // Unnumbered lines go to the tab stop the separator takes numbered ones to
+    if config.tabstop.is_some()
+        && config.separator.contains('\t')
+        && !leader.is_empty()
+        && !content.is_empty()
+    {
+        let stop = advance_str(column, &continuation_leader(config), tab);
+        if indent < stop {
+            write!(out, "{}", " ".repeat(stop - indent))?;
+            indent = stop;
+        }
+    }
+    let Some(width) = config.wrap else {
+        write!(out, "{content}")?;
+        return out.write_all(ending.as_bytes());
+    };
+
+    // Rows within a record end like it, or it may have no terminator at all
+    let row_end = if ending.is_empty() {
+        &config.line_end
+    } else {
+        ending
+    };
+    let mut column = indent;
+    let mut row_start = 0;
+    for (i, token) in ansi::tokens(content) {
+        // Escapes go out with the text around them, never starting a row
+        let ansi::Token::Char(c) = token else {
+            continue;
+        };
+        let mut next = advance(column, c, tab);
+        // Always place at least one character per row
+        if next > width && column > indent {
+            write!(out, "{}{row_end}", &content[row_start..i])?;
+            write!(out, "{}", " ".repeat(indent))?;
+            row_start = i;
+            next = advance(indent, c, tab);
+        }
+        column = next;
+    }
+    write!(out, "{}", &content[row_start..])?;
+    out.write_all(ending.as_bytes())
+}
+
+/// End an output line or record with `--output-record-sep`, `--crlf`, `--lf`
+/// or the default `\n`.
+fn end_line(out: &mut impl Write, config: &Config) -> io::Result<()> {
+    out.write_all(config.line_end.as_bytes())
+}
+
+/// What to end the output of a record with `terminator_len` bytes of
+/// terminator with: with `--keep-line-endings`, the `\n`, `\r\n` or nothing
+/// its input line ended with, else [`end_line`]'s terminator.
+fn record_ending(terminator_len: usize, config: &Config) -> &str {
+    if !config.keep_line_endings || config.record_separator != RecordSeparator::Newline {
+        return &config.line_end;
+    }
+    match terminator_len {
+        0 => "",
+        1 => "\n",
+        _ => "\r\n",
+    }
+}
+
+/// Write a line laid out for `--align-right-margin`: the content, then the
+/// number field (if any) ending at display column `margin`, with at least one
+/// space between them. `column` is the display column the content starts at,
+/// and `tab` the tab stop interval.
+fn write_right_aligned(
+    out: &mut impl Write,
+    column: usize,
+    content: &str,
+    number: Option<&str>,
+    margin: usize,
+    ending: &str,
+    tab: usize,
+) -> io::Result<()> {
+    let Some(number) = number else {
+        return write!(out, "{content}{ending}");
+    };
+    let number = number.trim_matches(' ');
+    let end = advance_str(column, content, tab);
+    let gap = margin.saturating_sub(end + width::str_width(number)).max(1);
+    write!(out, "{content}{}{number}{ending}", " ".repeat(gap))
+}
+
+/// How far through an input of `total` bytes `offset` is, as `NN%` padded
+/// to the width of `100%`.
+fn percent(offset: i64, total: Option<u64>) -> String {
+    let percent = match total {
+        Some(total) if total > 0 => (offset as u64 * 100 / total).min(100),
+        _ => 100,
+    };
+    format!("{percent:>3}%")
+}
+
+fn number_sign(n: i64, config: &Config) -> &'static str {
+    if n < 0 {
+        "-"
+    } else if config.show_sign && n > 0 {
+        "+"
+    } else {
+        ""
+    }
+}
+
+/// The digits of `n` (without its sign) in `base`, past 9 as a-z.
+fn radix_digits(n: i64, base: u32) -> String {
+    let mut rest = n.unsigned_abs();
+    let mut digits = Vec::new();
+    loop {
+        let digit = (rest % u64::from(base)) as u32;
+        digits.push(char::from_digit(digit, base).expect("digit is below the base"));
+        rest /= u64::from(base);
+        if rest == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
+/// The digits of line number `n`, in `--base`, `--number-case` and
+/// `--digits`.
+fn number_digits(n: i64, config: &Config) -> String {
+    let digits = radix_digits(n, config.base);
+    let digits = match config.number_case {
+        NumberCase::Lower => digits,
+        NumberCase::Upper => digits.to_ascii_uppercase(),
+    };
+    if config.zero_digit == '0' {
+        return digits;
+    }
+    let zero = u32::from(config.zero_digit);
+    digits
+        .chars()
+        .map(|c| match c.to_digit(10) {
+            Some(d) => char::from_u32(zero + d).expect("digit sets are contiguous"),
+            None => c,
+        })
+        .collect()
+}
+
+/// Expand tabs in `line` to spaces. A single tab stop repeats every that many
+/// columns; with a list, tabs past the last stop become single spaces.
+fn expand_tabs(line: &str, stops: &[usize]) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut column = 0;
+    for (_, token) in ansi::tokens(line) {
+        let c = match token {
+            ansi::Token::Escape(escape) => {
+                out.push_str(escape);
+                continue;
+            }
+            ansi::Token::Char(c) => c,
+        };
+        if c != '\t' {
+            out.push(c);
+            column += width::char_width(c);
+            continue;
+        }
+        let next = match stops {
+            [width] => (column / width + 1) * width,
+            _ => stops
+                .iter()
+                .copied()
+                .find(|&s| s > column)
+                .unwrap_or(column + 1),
+        };
+        out.extend(std::iter::repeat_n(' ', next - column));
+        column = next;
+    }
+    out
+}
+
+/// Render control characters in `cat -v` notation: `^X` for C0 controls,
+/// `^?` for DEL and `M-^X` for C1 controls. TAB and printable text are kept.
+fn show_nonprinting(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    for c in line.chars() {
+        match c as u32 {
+            0x09 => out.push(c),
+            code @ 0x00..=0x1F => {
+                out.push('^');
+                out.push(char::from(code as u8 + b'@'));
+            }
+            0x7F => out.push_str("^?"),
+            code @ 0x80..=0x9F => {
+                out.push_str("M-^");
+                out.push(char::from(code as u8 - 0x80 + b'@'));
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// `bytes` as text, with the bytes of invalid UTF-8 in `cat -v` notation:
+/// `M-` and the byte less 0x80, itself shown as `^X` if a control.
+fn meta_notation(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        text.push_str(chunk.valid());
+        for &b in chunk.invalid() {
+            let low = b & 0x7F;
+            if b >= 0x80 {
+                text.push_str("M-");
+            }
+            match low {
+                0x00..=0x1F => {
+                    text.push('^');
+                    text.push(char::from(low + b'@'));
+                }
+                0x7F => text.push_str("^?"),
+                _ => text.push(char::from(low)),
+            }
+        }
+    }
+    text
+}
+
+/// Marker appended to content cut short by `--max-line-length`.
+const TRUNCATION_MARKER: char = '…';
+
+/// Cut `content` to at most `max` display columns, the last of which is the
+/// truncation marker. Returns `None` if it already fits. Escape sequences
+/// are all kept, so colors cut off still get reset. Tab stops are every
+/// `tab` columns.
+fn truncate(content: &str, max: usize, tab: usize) -> Option<String> {
+    if advance_str(0, content, tab) <= max {
+        return None;
+    }
+    let mut column = 0;
+    let mut out = String::new();
+    let mut cut = None;
+    for (_, token) in ansi::tokens(content) {
+        match token {
+            ansi::Token::Escape(escape) => out.push_str(escape),
+            ansi::Token::Char(_) if cut.is_some() => {}
+            ansi::Token::Char(c) => {
+                column = advance(column, c, tab);
+                if column >= max {
+                    cut = Some(out.len());
+                } else {
+                    out.push(c);
+                }
+            }
+        }
+    }
+    out.insert(cut.unwrap_or(out.len()), TRUNCATION_MARKER);
+    Some(out)
+}
+
+/// Apply the output-only content transformations to a line.
+/// Classification (blank, pattern) always uses the original line.
+fn render_content<'a>(line: &'a str, config: &Config) -> Cow<'a, str> {
+    let mut content = Cow::Borrowed(line);
+    if let Some(stops) = &config.tab_stops
+        && content.contains('\t')
+    {
+        content = Cow::Owned(expand_tabs(&content, stops));
+    }
+    if config.show_nonprinting && content.chars().any(|c| c != '\t' && c.is_control()) {
+        content = Cow::Owned(show_nonprinting(&content));
+    }
+    if config.show_tabs && content.contains('\t') {
+        content = Cow::Owned(content.replace('\t', "^I"));
+    }
+    if let Some(max) = config.max_line_length
+        && let Some(truncated) = truncate(&content, max, tab_width(config))
+    {
+        content = Cow::Owned(truncated);
+        if config.report_length {
+            let length = line.chars().count();
+            content.to_mut().push_str(&format!(" [{length}]"));
+        }
+    }
+    if config.show_ends {
+        content.to_mut().push('$');
+    }
+    content
+}
+
+/// Whether `line` counts as blank for style `t` and `-l`. With
+/// `--blank-lines=whitespace`, NBSP, ideographic spaces and the like left
+/// over from copy and paste count as nothing.
+fn is_blank(line: &str, config: &Config) -> bool {
+    match config.blank_lines {
+        BlankLines::Empty => line.is_empty(),
+        BlankLines::Whitespace => line.chars().all(char::is_whitespace),
+        BlankLines::Invisible => line.chars().all(|c| c.is_whitespace() || is_invisible(c)),
+    }
+}
+
+/// Whether `c` takes no space and shows nothing: zero-width spaces and
+/// joiners, the BOM, the soft hyphen and bidirectional marks.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{180E}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{FEFF}'
+    )
+}
+
+/// Whether `line` looks blank but holds invisible characters, such as a
+/// stray zero-width space.
+fn has_only_invisible(line: &str) -> bool {
+    line.chars().any(is_invisible) && line.chars().all(|c| c.is_whitespace() || is_invisible(c))
+}
+
+/// The text of a record once `--renumber` and `--strip-ansi=output` have
+/// taken out what they remove.
+fn input_text(mut line: String, config: &Config) -> String {
+    if let Some(re) = &config.renumber
+        && let Some(m) = re.find(&line).filter(|m| m.start() == 0)
+    {
+        line.drain(..m.end());
+    }
+    if config.strip_ansi == Some(StripAnsi::Output)
+        && let Cow::Owned(stripped) = ansi::strip(&line)
+    {
+        line = stripped;
+    }
+    line
+}
+
+/// `line` as blank checks and regexes see it: without ANSI escapes under
+/// `--strip-ansi`, and in the `--normalize` form, if given.
+fn for_matching<'a>(line: &'a str, config: &Config) -> Cow<'a, str> {
+    let line = match config.strip_ansi {
+        Some(_) => ansi::strip(line),
+        None => Cow::Borrowed(line),
+    };
+    match config.normalize {
+        // Nothing in ASCII decomposes or combines
+        Some(form) if !line.is_ascii() => Cow::Owned(normalize::normalize(&line, form)),
+        _ => line,
+    }
+}
+
+/// `line` is numbered by `style`. With `--match-threads`, `p` style
+/// results are looked up in `ahead` rather than worked out here.
+fn should_number(line: &str, style: &Style, ahead: Option<&Matched>, config: &Config) -> bool {
+    match style {
+        Style::All => true,
+        Style::NonEmpty => !is_blank(line, config),
+        Style::None => false,
+        Style::Pattern(re) => match ahead.and_then(|ahead| ahead.get(re)) {
+            Some(matched) => matched,
+            None => re.is_match(line),
+        },
+        Style::And(styles) => styles
+            .iter()
+            .all(|style| should_number(line, style, ahead, config)),
+        Style::Or(styles) => styles
+            .iter()
+            .any(|style| should_number(line, style, ahead, config)),
+        Style::Not(style) => !should_number(line, style, ahead, config),
+    }
+}
+
+/// Whether `style` numbers the blank `line` (before `-l` grouping). Only
+/// `a` of the single styles does; an expression is worked out for the line.
+fn numbers_blank(line: &str, style: &Style, ahead: Option<&Matched>, config: &Config) -> bool {
+    match style {
+        Style::All => true,
+        Style::And(_) | Style::Or(_) | Style::Not(_) => should_number(line, style, ahead, config),
+        _ => false,
+    }
+}
+
+/// Lines read ahead per `--match-threads` thread.
+const MATCH_BATCH: usize = 1024;
+
+/// How the `p` style regexes in use went for one line.
+struct Matched<'a> {
+    patterns: &'a [&'a Regex],
+    results: Vec<bool>, // one per pattern
+}
+
+impl Matched<'_> {
+    fn get(&self, re: &Regex) -> Option<bool> {
+        let i = self.patterns.iter().position(|p| std::ptr::eq(*p, re))?;
+        Some(self.results[i])
+    }
+}
+
+/// `--match-threads`: reads records in batches and tries the `p` style
+/// regexes on each on a pool of threads, so that slow patterns do not
+/// hold up numbering. The numbering itself stays in order on one thread.
+struct MatchAhead<'a> {
+    patterns: Vec<&'a Regex>,
+    batch: VecDeque<(InputLine, Vec<bool>)>,
+    threads: usize,
+}
+
+impl<'a> MatchAhead<'a> {
+    /// `None` unless `--match-threads` and some `p` style are both given.
+    /// Timestamps are taken as lines arrive, so they rule out reading ahead.
+    fn new(config: &'a Config) -> Option<Self> {
+        if config.match_threads < 2 || config.timestamp.is_some() {
+            return None;
+        }
+        let styles = [
+            &config.header_style,
+            &config.body_style,
+            &config.footer_style,
+        ];
+        let levels = config.section_levels.iter().map(|level| &level.style);
+        let patterns: Vec<&Regex> = styles
+            .into_iter()
+            .chain(levels)
+            .flat_map(Style::patterns)
+            .collect();
+        (!patterns.is_empty()).then_some(MatchAhead {
+            patterns,
+            batch: VecDeque::new(),
+            threads: config.match_threads,
+        })
+    }
+
+    /// The next record with its results, reading another batch if needed.
+    fn next(
+        &mut self,
+        buf: &mut impl BufRead,
+        bytes: &mut Vec<u8>,
+        config: &Config,
+    ) -> io::Result<Option<(InputLine, Matched<'_>)>> {
+        if self.batch.is_empty() {
+            let mut records = Vec::new();
+            while records.len() < MATCH_BATCH * self.threads {
+                match read_record(
+                    buf,
+                    bytes,
+                    &config.record_separator,
+                    config.max_memory,
+                    config.show_nonprinting,
+                )? {
+                    Some(record) => records.push(record),
+                    None => break,
+                }
+            }
+            let per_thread = records.len().div_ceil(self.threads).max(1);
+            let patterns = &self.patterns;
+            let results: Vec<Vec<bool>> = thread::scope(|scope| {
+                let workers: Vec<_> = records
+                    .chunks(per_thread)
+                    .map(|chunk| {
+                        scope.spawn(move || {
+                            chunk
+                                .iter()
+                                .map(|record| {
+                                    let line = input_text(record.text.clone(), config);
+                                    let matched = for_matching(&line, config);
+                                    patterns.iter().map(|re| re.is_match(&matched)).collect()
+                                })
+                                .collect::<Vec<Vec<bool>>>()
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .flat_map(|worker| worker.join().expect("match thread panicked"))
+                    .collect()
+            });
+            self.batch.extend(records.into_iter().zip(results));
+        }
+        Ok(self.batch.pop_front().map(|(record, results)| {
+            let matched = Matched {
+                patterns: &self.patterns,
+                results,
+            };
+            (record, matched)
+        }))
+    }
+}
+
+/// Indentation level of `line` for `--indent-sections`: its leading
+/// whitespace in columns over `unit`, or with a `unit` of 0, 1 for any
+/// indentation at all.
+fn indent_depth(line: &str, unit: usize) -> usize {
+    let columns = line
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .fold(0, |column, c| advance(column, c, TERMINAL_TAB_WIDTH));
+    columns
+        .checked_div(unit)
+        .unwrap_or(usize::from(columns > 0))
+}
+
+/// Set once any non-fatal warning has been issued; the run then exits 1.
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Report a non-fatal problem. `--quiet` hides the message but not the
+/// nonzero exit status.
+fn warn(config: &Config, code: &str, path: &str, line: Option<usize>, message: &str) {
+    WARNINGS.fetch_add(1, Ordering::Relaxed);
+    if !config.quiet {
+        diagnostic::report(code, Some(path), line, message);
+    }
+}
+
+/// With `--debug`, report how input line `line` of `path` was handled.
+fn trace(config: &Config, path: &str, line: usize, message: &str) {
+    if config.debug {
+        diagnostic::report(
+            "debug",
+            Some(path),
+            Some(line),
+            &format!("debug: {message}"),
+        );
+    }
+}
+
+/// Size of the initial block sniffed for NUL bytes by binary detection.
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// Peek at the first block of input and report whether it looks binary,
+/// i.e. contains a NUL byte. Nothing is consumed from the reader.
+fn is_binary(buf: &mut impl BufRead) -> io::Result<bool> {
+    let block = buf.fill_buf()?;
+    let len = block.len().min(BINARY_SNIFF_LEN);
+    Ok(block[..len].contains(&0))
+}
+
+/// One line of input, without its terminator.
+struct InputLine {
+    text: String,
+    /// Length in bytes of the raw line, including its terminator.
+    raw_len: usize,
+    /// Length in bytes of the stripped `\n` or `\r\n` (0 at end of input).
+    terminator_len: usize,
+    /// Length in bytes of input skipped before the text (paragraph mode).
+    leading_len: usize,
+    /// Whether invalid UTF-8 in the line was replaced with U+FFFD.
+    replaced: bool,
+}
+
+impl InputLine {
+    /// The line read as `bytes`. Invalid UTF-8 is replaced with U+FFFD, or
+    /// with `nonprinting` (`--show-nonprinting`) shown in `M-` notation.
+    fn from_bytes(bytes: &[u8], raw_len: usize, nonprinting: bool) -> InputLine {
+        let text = String::from_utf8_lossy(bytes);
+        let invalid = matches!(text, Cow::Owned(_));
+        let text = if invalid && nonprinting {
+            meta_notation(bytes)
+        } else {
+            text.into_owned()
+        };
+        InputLine {
+            replaced: invalid && !nonprinting,
+            text,
+            raw_len,
+            terminator_len: raw_len - bytes.len(),
+            leading_len: 0,
+        }
+    }
+}
+
+/// `read_until`, but with `--max-memory` failing once `bytes` would grow past
+/// `limit` rather than buffering an endless record.
+fn read_until_limited(
+    buf: &mut impl BufRead,
+    byte: u8,
+    bytes: &mut Vec<u8>,
+    limit: Option<usize>,
+) -> io::Result<usize> {
+    let Some(limit) = limit else {
+        return buf.read_until(byte, bytes);
+    };
+    let room = (limit + 1).saturating_sub(bytes.len());
+    let n = buf.take(room as u64).read_until(byte, bytes)?;
+    if bytes.len() > limit {
+        return Err(record_too_long(limit));
+    }
+    Ok(n)
+}
+
+fn record_too_long(limit: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::OutOfMemory,
+        format!("record longer than --max-memory ({limit} bytes)"),
+    )
+}
+
+/// Read one line, stripping the trailing `\n` or `\r\n` like `BufRead::lines`.
+/// Invalid UTF-8 is replaced rather than rejected so binary input can be numbered.
+fn read_line(
+    buf: &mut impl BufRead,
+    bytes: &mut Vec<u8>,
+    limit: Option<usize>,
+    nonprinting: bool,
+) -> io::Result<Option<InputLine>> {
+    bytes.clear();
+    let raw_len = read_until_limited(buf, b'\n', bytes, limit)?;
+    if raw_len == 0 {
+        return Ok(None);
+    }
+    if bytes.last() == Some(&b'\n') {
+        bytes.pop();
+        if bytes.last() == Some(&b'\r') {
+            bytes.pop();
+        }
+    }
+    Ok(Some(InputLine::from_bytes(bytes, raw_len, nonprinting)))
+}
+
+/// Whether the unread input starts with an empty line.
+fn starts_with_blank_line(buf: &mut impl BufRead) -> io::Result<bool> {
+    let block = buf.fill_buf()?;
+    Ok(block.starts_with(b"\n") || block.starts_with(b"\r\n"))
+}
+
+/// Read one record delimited by `separator`, with the separator stripped,
+/// decoded as [`InputLine::from_bytes`] says.
+fn read_record(
+    buf: &mut impl BufRead,
+    bytes: &mut Vec<u8>,
+    separator: &RecordSeparator,
+    limit: Option<usize>,
+    nonprinting: bool,
+) -> io::Result<Option<InputLine>> {
+    match separator {
+        RecordSeparator::Newline => read_line(buf, bytes, limit, nonprinting),
+        RecordSeparator::Paragraph => {
+            // Blank lines before the first paragraph belong to no record
+            let mut leading_len = 0;
+            let mut record = loop {
+                match read_line(buf, bytes, limit, nonprinting)? {
+                    None => return Ok(None),
+                    Some(line) if line.text.is_empty() => leading_len += line.raw_len,
+                    Some(line) => break line,
+                }
+            };
+            record.leading_len = leading_len;
+            record.raw_len += leading_len;
+            // The paragraph ends at a run of blank lines, which is its terminator
+            let mut ended = false;
+            while let Some(line) = read_line(buf, bytes, limit, nonprinting)? {
+                record.raw_len += line.raw_len;
+                if let Some(limit) = limit
+                    && record.raw_len > limit
+                {
+                    return Err(record_too_long(limit));
+                }
+                if line.text.is_empty() {
+                    record.terminator_len += line.raw_len;
+                    ended = true;
+                } else {
+                    record.text.push('\n');
+                    record.text.push_str(&line.text);
+                    record.terminator_len = line.terminator_len;
+                }
+                if ended && !starts_with_blank_line(buf)? {
+                    break;
+                }
+            }
+            Ok(Some(record))
+        }
+        RecordSeparator::Delimiter(delim) => {
+            bytes.clear();
+            let last = delim[delim.len() - 1];
+            loop {
+                if read_until_limited(buf, last, bytes, limit)? == 0 || bytes.ends_with(delim) {
+                    break;
+                }
+            }
+            if bytes.is_empty() {
+                return Ok(None);
+            }
+            let raw_len = bytes.len();
+            if bytes.ends_with(delim) {
+                bytes.truncate(raw_len - delim.len());
+            }
+            Ok(Some(InputLine::from_bytes(bytes, raw_len, nonprinting)))
+        }
+    }
+}
+
+/// Number the file at `path` (`-` for stdin) onto `out`, going on from
+/// `carry`.
+fn number_file(
+    path: &str,
+    out: &mut impl Write,
+    config: &Config,
+    carry: Carry,
+) -> io::Result<Tally> {
+    let started = Instant::now();
+    if path == "-" {
+        let tally = process_input(io::stdin(), "standard input", None, out, config, carry)?;
+        return Ok(Tally {
+            elapsed: started.elapsed(),
+            ..tally
+        });
+    }
+    let file =
+        readahead::open_sequential(Path::new(path)).map_err(|e| diagnostic::with_path(e, path))?;
+    let size = file
+        .metadata()
+        .ok()
+        .filter(|m| m.is_file())
+        .map(|m| m.len());
+    let result = match uring_reader(&file, size) {
+        Some(reader) => process_input(reader, path, size, out, config, carry),
+        None => process_input(&file, path, size, out, config, carry),
+    };
+    if config.drop_cache {
+        readahead::drop_cached(&file);
+    }
+    Ok(Tally {
+        elapsed: started.elapsed(),
+        ..result?
+    })
+}
+
+/// With the `io-uring` feature, a reader that keeps reads of a regular file
+/// in flight with io_uring, if the kernel allows it.
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+fn uring_reader(file: &File, size: Option<u64>) -> Option<uring::UringReader<'_>> {
+    size?;
+    uring::UringReader::new(file).ok()
+}
+
+#[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+fn uring_reader(_: &File, _: Option<u64>) -> Option<io::Empty> {
+    None
+}
+
+/// Number `paths` with `--threads`: workers number whole files into memory
+/// (or a temporary file past `--max-memory`), and each file is written out
+/// once those before it are, so the output is the same as numbering them
+/// one after another.
+fn number_files_parallel<W: Write>(
+    paths: &[String],
+    out: &mut WatchedWriter<W>,
+    config: &Config,
+) -> Vec<io::Result<Tally>> {
+    let next = &AtomicUsize::new(0);
+    let stop = &AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..config.threads.min(paths.len()) {
+            let sender = sender.clone();
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        break;
+                    };
+                    let mut numbered = SpillBuffer::new(config.max_memory);
+                    let result = number_file(path, &mut numbered, config, Carry::start(config))
+                        .map(|tally| (numbered, tally));
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Files finish in any order; hold each until its turn
+        let mut finished = BTreeMap::new();
+        let mut results = Vec::new();
+        for (index, result) in receiver.iter() {
+            finished.insert(index, result);
+            while let Some(result) = finished.remove(&results.len()) {
+                let (result, stopping) = match result {
+                    Ok((numbered, tally)) => {
+                        let copied = numbered.copy_to(out).map(|()| tally);
+                        let stopping = copied.is_err();
+                        (copied, stopping)
+                    }
+                    Err(e) => (
+                        Err(e),
+                        config.fail_fast || out.failed || interrupt::requested(),
+                    ),
+                };
+                results.push(result);
+                if stopping {
+                    stop.store(true, Ordering::Relaxed);
+                    return results;
+                }
+            }
+        }
+        results
+    })
+}
+
+/// Number `paths` in turn, going on past inputs that cannot be read unless
+/// `--fail-fast` is given. Failing to write the output always ends the run.
+fn number_files<W: Write>(
+    paths: &[String],
+    out: &mut WatchedWriter<W>,
+    config: &Config,
+) -> Vec<io::Result<Tally>> {
+    // Each file goes on from where the one before ended, unless restarting
+    if config.threads > 1 && paths.len() > 1 && config.restart_per_file {
+        return number_files_parallel(paths, out, config);
+    }
+    let mut results = Vec::new();
+    let mut carry = Carry::start(config);
+    for path in paths {
+        let result = number_file(path, out, config, carry);
+        if !config.restart_per_file
+            && let Ok(Tally {
+                carry: Some(next), ..
+            }) = result
+        {
+            carry = next;
+        }
+        let stopping =
+            result.is_err() && (config.fail_fast || out.failed || interrupt::requested());
+        results.push(result);
+        if stopping {
+            break;
+        }
+    }
+    results
+}
+
+/// Writer that notes whether writing to `inner` has failed, to tell errors
+/// in the output apart from errors reading an input.
+struct WatchedWriter<W> {
+    inner: W,
+    failed: bool,
+}
+
+impl<W: Write> Write for WatchedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf);
+        self.failed |= written.is_err();
+        written
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let flushed = self.inner.flush();
+        self.failed |= flushed.is_err();
+        flushed
+    }
+}
+
+/// How an input fared: `succeeded`, `skipped` (as binary) or `failed`.
+fn status(result: &io::Result<Tally>) -> &'static str {
+    match result {
+        Ok(tally) if tally.skipped => "skipped",
+        Ok(_) => "succeeded",
+        Err(_) => "failed",
+    }
+}
+
+/// The stderr line summing up a multi-file run in which some input was
+/// skipped or failed, like `3 inputs: 1 succeeded, 1 skipped, 1 failed`.
+/// Inputs after a `--fail-fast` stop are counted as not read.
+fn status_line(inputs: usize, results: &[io::Result<Tally>]) -> Option<String> {
+    let count = |wanted| results.iter().filter(|r| status(r) == wanted).count();
+    let (succeeded, skipped, failed) = (count("succeeded"), count("skipped"), count("failed"));
+    if inputs < 2 || succeeded == inputs {
+        return None;
+    }
+    let mut line =
+        format!("{inputs} inputs: {succeeded} succeeded, {skipped} skipped, {failed} failed");
+    if results.len() < inputs {
+        line.push_str(&format!(", {} not read", inputs - results.len()));
+    }
+    Some(line)
+}
+
+/// Resolve the input encoding (stripping any BOM) and apply the binary
+/// policy to `reader`, then number it. `name` is used in diagnostics only;
+/// `size` is the input's length if known, for `--progress`.
+fn process_input(
+    reader: impl Read,
+    name: &str,
+    size: Option<u64>,
+    out: &mut impl Write,
+    config: &Config,
+    carry: Carry,
+) -> io::Result<Tally> {
+    let reader = interrupt::InterruptibleReader::new(reader);
+    let mut buf = BufReader::new(ProgressReader::new(reader, config.progress.then_some(size)));
+    let mut out = EncodingWriter::new(out, config.output_encoding);
+
+    let (input_encoding, had_bom) = encoding::detect(&mut buf, config.input_encoding)?;
+    // Latin-1 and Shift_JIS cannot represent U+FEFF, so there is no BOM to re-emit
+    if config.keep_bom && had_bom && config.output_encoding.has_bom() {
+        write!(out, "\u{FEFF}")?;
+    }
+
+    // UTF-16 text is full of NUL bytes, so only sniff byte-oriented input
+    if !input_encoding.is_utf16()
+        && config.binary_policy != BinaryPolicy::Process
+        && is_binary(&mut buf)?
+    {
+        if config.binary_policy == BinaryPolicy::Skip {
+            warn(config, "binary", name, None, "binary file, skipping");
+            return Ok(Tally {
+                skipped: true,
+                ..Tally::default()
+            });
+        }
+        warn(config, "binary", name, None, "warning: binary file");
+    }
+
+    if input_encoding == Encoding::Utf8 {
+        number_lines(buf, &mut out, name, size, config, carry)
+    } else {
+        // Offsets count decoded bytes, which the file size does not tell
+        let decoded = BufReader::new(DecodingReader::new(buf, input_encoding));
+        number_lines(decoded, &mut out, name, None, config, carry)
+    }
+}
+
+/// Number the elements of a JSON array instead of lines (`--json-array`).
+fn number_json_array(
+    buf: impl BufRead,
+    out: &mut impl Write,
+    mode: JsonOutput,
+    config: &Config,
+    carry: Carry,
+) -> io::Result<Tally> {
+    let mut tally = Tally::default();
+    let mut number = carry.number;
+    for element in json::ArrayElements::new(buf) {
+        let element = element?;
+        match mode {
+            JsonOutput::Lines => write!(
+                out,
+                "{}{}{}",
+                gutter(Some(number), config),
+                config.separator,
+                element
+            )?,
+            JsonOutput::Annotate => write!(
+                out,
+                "{}",
+                JsonObject::new()
+                    .raw("number", number)
+                    .raw("value", element)
+                    .finish()
+            )?,
+        }
+        end_line(out, config)?;
+        tally.numbered += 1;
+        tally.body += 1;
+        tally.first.get_or_insert(number);
+        tally.last = Some(number);
+        tally.widest = tally.widest.max(number_len(number, config));
+        number += config.increment;
+    }
+    tally.carry = Some(Carry { number, ..carry });
+    Ok(tally)
+}
+
+/// Recognizes the number field nl writes with the configured prefix, suffix,
+/// format and separator, for `--strip` and `--check`.
+struct NumberField {
+    number: Regex,
+}
+
+/// A line of nl output split into its number field and text.
+struct FieldMatch<'a> {
+    number: i64,
+    /// Byte length of the number field and its padding, without separator.
+    end: usize,
+    text: &'a str,
+}
+
+impl NumberField {
+    fn new(config: &Config) -> Self {
+        let mut digits = match config.base {
+            2..=10 => format!("0-{}", config.base - 1),
+            base => {
+                let last = char::from_digit(base - 1, base).expect("digit is below the base");
+                format!("0-9a-{last}A-{}", last.to_ascii_uppercase())
+            }
+        };
+        if config.zero_digit != '0' {
+            let zero = config.zero_digit;
+            let nine = char::from_u32(u32::from(zero) + config.base.min(10) - 1)
+                .expect("digit sets are contiguous");
+            digits.push_str(&format!("{zero}-{nine}"));
+        }
+        let number = Regex::new(&format!(
+            r"^{} *([+-]?[{digits}]+){}",
+            regex::escape(&config.number_prefix),
+            regex::escape(&config.number_suffix),
+        ))
+        .expect("escaped pattern is valid");
+        NumberField { number }
+    }
+
+    /// Whether `line` is one nl wrote: numbered, or only the padding of an
+    /// unnumbered blank line, which `RENUMBER_PATTERN` strips too.
+    fn is_numbered(&self, line: &str, config: &Config) -> bool {
+        (!line.is_empty() && line.bytes().all(|b| b == b' ')) || self.find(line, config).is_some()
+    }
+
+    fn find<'a>(&self, line: &'a str, config: &Config) -> Option<FieldMatch<'a>> {
+        let caps = self.number.captures(line)?;
+        let mut end = caps.get(0)?.end();
+        // Left-aligned and centered numbers are padded to -w after the suffix
+        if matches!(config.number_format, Format::Left | Format::Center) {
+            let used = width::str_width(&line[config.number_prefix.len()..end]);
+            let padding = (config.number_width + width::str_width(&config.number_suffix))
+                .saturating_sub(used);
+            let spaces = line[end..].len() - line[end..].trim_start_matches(' ').len();
+            end += padding.min(spaces);
+        }
+        Some(FieldMatch {
+            number: i64::from_str_radix(&ascii_digits(&caps[1], config), config.base).ok()?,
+            end,
+            text: line[end..].strip_prefix(config.separator.as_str())?,
+        })
+    }
+}
+
+/// `--strip`: write each line without the number field nl gave it. The
+/// width of right-aligned fields is learned from the first numbered line, so
+/// it need not be given again; unnumbered lines lose that many columns of
+/// padding. Left-aligned numbers are padded to `-w` after the number.
+fn strip_numbers(mut buf: impl BufRead, out: &mut impl Write, config: &Config) -> io::Result<()> {
+    let field = NumberField::new(config);
+    let mut width = width::str_width(&gutter(None, config));
+
+    let mut bytes = Vec::new();
+    while let Some(input) = read_record(
+        &mut buf,
+        &mut bytes,
+        &config.record_separator,
+        config.max_memory,
+        false,
+    )? {
+        if interrupt::requested() {
+            return Err(interrupt::error());
+        }
+        let line = input.text;
+        let text = match field.find(&line, config) {
+            Some(found) => {
+                width = width::str_width(&line[..found.end]);
+                found.text
+            }
+            None => {
+                let padding = line.len() - line.trim_start_matches(' ').len();
+                &line[padding.min(width)..]
+            }
+        };
+        write!(out, "{text}")?;
+        end_line(out, config)?;
+    }
+    Ok(())
+}
+
+/// `--check`: read nl output and warn about every number that does not
+/// follow from `-v` and `-i`: gaps, repeats and numbers going backwards. A
+/// return to the starting number is taken as a new section unless `-p`.
+fn check_numbers(mut buf: impl BufRead, name: &str, config: &Config) -> io::Result<()> {
+    let field = NumberField::new(config);
+    let mut expected = config.start_number;
+    let mut seen: HashMap<i64, usize> = HashMap::new();
+
+    let mut bytes = Vec::new();
+    let mut line_number = 0;
+    while let Some(input) = read_record(
+        &mut buf,
+        &mut bytes,
+        &config.record_separator,
+        config.max_memory,
+        false,
+    )? {
+        if interrupt::requested() {
+            return Err(interrupt::error());
+        }
+        line_number += 1;
+        let Some(found) = field.find(&input.text, config) else {
+            continue;
+        };
+        let n = found.number;
+        if n == config.start_number && !config.no_renumber {
+            expected = n;
+            seen.clear();
+        }
+        if n != expected {
+            let problem = if let Some(first) = seen.get(&n) {
+                format!("duplicate number {n} (first on line {first})")
+            } else if (n - expected).signum() != config.increment.signum() {
+                format!("number {n} out of order, expected {expected}")
+            } else {
+                format!("gap before {n}, expected {expected}")
+            };
+            warn(config, "numbering", name, Some(line_number), &problem);
+        }
+        seen.entry(n).or_insert(line_number);
+        expected = n + config.increment;
+    }
+    Ok(())
+}
+
+/// Whether the options leave every line but section delimiters exactly as
+/// read: only [`COPY_SAFE`] options were given, nothing is numbered and
+/// unnumbered lines are not padded. nl is then just `cat` and can copy
+/// blocks.
+fn passes_through(config: &Config) -> bool {
+    let styles = [
+        &config.header_style,
+        &config.body_style,
+        &config.footer_style,
+    ];
+    config.copy_safe
+        && styles.iter().all(|s| matches!(s, Style::None))
+        && (!config.pad_unnumbered || gutter(None, config).is_empty())
+}
+
+/// Copy input to output a block at a time, for [`passes_through`]. Output
+/// matches the line-by-line path: `\r\n` becomes `\n`, invalid UTF-8 is
+/// replaced, section delimiters become empty lines, and the last line gets
+/// a terminator. Only blocks that need those fixes are split into lines.
+fn copy_through(
+    mut buf: impl BufRead,
+    out: &mut impl Write,
+    name: &str,
+    config: &Config,
+) -> io::Result<()> {
+    let mut replaced = false;
+    let pair: String = config.section_delimiter.iter().collect();
+    let delimiters = config.sections && !pair.is_empty();
+    let mut write_lines = |bytes: &[u8], out: &mut dyn Write| -> io::Result<()> {
+        let maybe_delimiter = delimiters
+            && bytes
+                .windows(pair.len())
+                .any(|window| window == pair.as_bytes());
+        if !maybe_delimiter && !bytes.contains(&b'\r') && std::str::from_utf8(bytes).is_ok() {
+            out.write_all(bytes)?;
+            if !bytes.ends_with(b"\n") {
+                out.write_all(b"\n")?;
+            }
+            return Ok(());
+        }
+        for line in bytes.split_inclusive(|&b| b == b'\n') {
+            let line = line
+                .strip_suffix(b"\n")
+                .map_or(line, |l| l.strip_suffix(b"\r").unwrap_or(l));
+            let text = String::from_utf8_lossy(line);
+            if delimiters
+                && delimiter_repeats(text.as_bytes(), pair.as_bytes())
+                    .and_then(|n| section_for_repeats(n, config))
+                    .is_some()
+            {
+                writeln!(out)?;
+                continue;
+            }
+            if matches!(text, Cow::Owned(_)) && !replaced {
+                replaced = true;
+                warn(
+                    config,
+                    "invalid-utf8",
+                    name,
+                    None,
+                    "warning: invalid UTF-8 replaced with U+FFFD",
+                );
+            }
+            writeln!(out, "{text}")?;
+        }
+        Ok(())
+    };
+
+    // The unfinished last line of the blocks read so far
+    let mut partial = Vec::new();
+    loop {
+        if interrupt::requested() {
+            return Err(interrupt::error());
+        }
+        let block = buf.fill_buf()?;
+        if block.is_empty() {
+            break;
+        }
+        let len = block.len();
+        let lines_end = block.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        if lines_end > 0 {
+            if partial.is_empty() {
+                write_lines(&block[..lines_end], out)?;
+            } else {
+                partial.extend_from_slice(&block[..lines_end]);
+                write_lines(&partial, out)?;
+                partial.clear();
+            }
+        }
+        partial.extend_from_slice(&block[lines_end..]);
+        buf.consume(len);
+    }
+    if !partial.is_empty() {
+        write_lines(&partial, out)?;
+    }
+    Ok(())
+}
+
+/// Read the rest of `buf` into memory, within `--max-memory`.
+fn read_all(mut buf: impl BufRead, config: &Config) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    match config.max_memory {
+        Some(limit) => {
+            buf.take(limit as u64 + 1).read_to_end(&mut data)?;
+            if data.len() > limit {
+                return Err(record_too_long(limit));
+            }
+        }
+        None => {
+            buf.read_to_end(&mut data)?;
+        }
+    }
+    Ok(data)
+}
+
+/// Number `buf`, whose length in bytes is `size` if known, going on from
+/// `carry` unless `--continue-from-input` finds a number to go on from.
+fn number_lines(
+    buf: impl BufRead,
+    out: &mut impl Write,
+    name: &str,
+    size: Option<u64>,
+    config: &Config,
+    carry: Carry,
+) -> io::Result<Tally> {
+    if let Some(mode) = config.json_array {
+        return number_json_array(buf, out, mode, config, carry);
+    }
+    if config.strip {
+        strip_numbers(buf, out, config)?;
+        return Ok(Tally::default());
+    }
+    if config.check {
+        check_numbers(buf, name, config)?;
+        return Ok(Tally::default());
+    }
+    if passes_through(config) {
+        copy_through(buf, out, name, config)?;
+        return Ok(Tally::default());
+    }
+    let wants_total = config
+        .columns
+        .as_ref()
+        .is_some_and(|columns| columns.contains(&Column::Percent));
+    let realign = config.overflow == Overflow::GrowRealign && config.number_width > 0;
+    if config.continue_from.is_none() && !realign && !(wants_total && size.is_none()) {
+        return number_records(buf, out, name, size, config, carry);
+    }
+
+    // The number to continue from may be anywhere, the widest number is only
+    // known at the end and a pipe has no size up front: take in all input
+    let data = read_all(buf, config)?;
+    let total = Some(data.len() as u64);
+    let number = match config.continue_from {
+        Some(which) => {
+            let field = NumberField::new(config);
+            let mut records = io::Cursor::new(&data);
+            let mut bytes = Vec::new();
+            let mut found = Vec::new();
+            while let Some(record) = read_record(
+                &mut records,
+                &mut bytes,
+                &config.record_separator,
+                None,
+                false,
+            )? {
+                found.extend(field.find(&record.text, config).map(|m| m.number));
+            }
+            let mut found = found.into_iter();
+            let last = match which {
+                ContinueFrom::First => found.next(),
+                ContinueFrom::Last => found.next_back(),
+            };
+            last.map_or(carry.number, |n| n + config.increment)
+        }
+        None => carry.number,
+    };
+    let carry = Carry { number, ..carry };
+    if realign {
+        // A silent dry run finds the widest number
+        let dry_run = Config {
+            quiet: true,
+            ..config.clone()
+        };
+        let widest = number_records(
+            io::Cursor::new(&data),
+            &mut io::sink(),
+            name,
+            total,
+            &dry_run,
+            carry,
+        )?
+        .widest;
+        let widened = Config {
+            number_width: config.number_width.max(widest),
+            ..config.clone()
+        };
+        return number_records(io::Cursor::new(&data), out, name, total, &widened, carry);
+    }
+    number_records(io::Cursor::new(data), out, name, total, config, carry)
+}
+
+/// Where numbering stands between inputs, so that several of them are
+/// numbered as one stream unless `--restart-per-file`.
+#[derive(Clone, Copy)]
+struct Carry {
+    number: i64,        // the number the next numbered line gets
+    section: Section,   // the section the input ended in
+    blank_count: usize, // blank lines it ended with, for -l
+}
+
+impl Carry {
+    /// Where numbering starts, before any input.
+    fn start(config: &Config) -> Carry {
+        Carry {
+            number: config.start_number,
+            section: Section::Body,
+            blank_count: 0,
+        }
+    }
+}
+
+/// What numbering one input came to.
+#[derive(Clone, Copy, Default)]
+struct Tally {
+    numbered: u64,        // lines given a number
+    header: u64,          // of those, lines in header sections
+    body: u64,            // lines in body sections
+    footer: u64,          // lines in footer sections
+    first: Option<i64>,   // the first number given
+    last: Option<i64>,    // the last number given
+    widest: usize,        // columns taken by the widest line number
+    elapsed: Duration,    // time taken
+    skipped: bool,        // input passed over as binary by --binary=skip
+    carry: Option<Carry>, // where numbering stood at the end of the input
+}
+
+impl Tally {
+    fn add(&mut self, other: Tally) {
+        self.numbered += other.numbered;
+        self.header += other.header;
+        self.body += other.body;
+        self.footer += other.footer;
+        self.first = self.first.or(other.first);
+        self.last = other.last.or(self.last);
+        self.widest = self.widest.max(other.widest);
+        self.elapsed += other.elapsed;
+    }
+}
+
+/// `number` with any `--digits` digits turned back into 0-9.
+fn ascii_digits<'a>(number: &'a str, config: &Config) -> Cow<'a, str> {
+    if config.zero_digit == '0' {
+        return Cow::Borrowed(number);
+    }
+    let zero = u32::from(config.zero_digit);
+    Cow::Owned(
+        number
+            .chars()
+            .map(
+                |c| match u32::from(c).checked_sub(zero).filter(|&d| d < 10) {
+                    Some(d) => char::from_digit(d, 10).expect("below 10"),
+                    None => c,
+                },
+            )
+            .collect(),
+    )
+}
+
+/// Columns the sign and digits of line number `n` take.
+fn number_len(n: i64, config: &Config) -> usize {
+    number_sign(n, config).len() + width::str_width(&number_digits(n, config))
+}
+
+/// Number the records of `buf`, going on from `carry`.
+/// `total` is the input length in bytes if known, for the percent column.
+fn number_records(
+    mut buf: impl BufRead,
+    out: &mut impl Write,
+    name: &str,
+    total: Option<u64>,
+    config: &Config,
+    carry: Carry,
+) -> io::Result<Tally> {
+    let mut line_number = carry.number;
+    let mut global_number = config.start_number;
+
+    let mut current_section = carry.section;
+    // With --indent-sections, the next number at each level of the current
+    // outline path; leaving a level restarts the numbering of those below it
+    let mut depth = 0;
+    let mut depth_numbers = vec![carry.number];
+    let mut blank_count = carry.blank_count;
+    let mut prev_blank = false;
+    let mut in_paragraph = false;
+    let mut continued = false;
+    let mut in_quotes = false;
+    let mut fence = None;
+    let mut in_region = false;
+    let mut in_entry = false;
+    let mut hunk: Option<Hunk> = None;
+    let mut first_numbers: HashMap<String, i64> = HashMap::new();
+    let mut remembered: usize = 0;
+    let mut comment_scanner = config.comments.clone().map(CommentScanner::new);
+    let mut position: i64 = 0;
+    let mut byte_offset: i64 = 0;
+    let mut tally = Tally::default();
+
+    let delimiter_pair: String = config.section_delimiter.iter().collect();
+
+    let clock = config.timestamp.as_deref().map(Clock::new);
+    let mut replaced = false;
+
+    let numbered = config.continue_from.map(|_| NumberField::new(config));
+
+    let mut match_ahead = MatchAhead::new(config);
+    let mut bytes = Vec::new();
+    let mut input_line: usize = 0;
+    loop {
+        // When annotating a stream, show each line as soon as it is read
+        if clock.is_some() {
+            out.flush()?;
+        }
+        if interrupt::requested() {
+            return Err(interrupt::error());
+        }
+        let (input, ahead) = match &mut match_ahead {
+            Some(match_ahead) => match match_ahead.next(&mut buf, &mut bytes, config)? {
+                Some((input, matched)) => (input, Some(matched)),
+                None => break,
+            },
+            None => match read_record(
+                &mut buf,
+                &mut bytes,
+                &config.record_separator,
+                config.max_memory,
+                config.show_nonprinting,
+            )? {
+                Some(input) => (input, None),
+                None => break,
+            },
+        };
+        let stamp = clock.as_ref().map(Clock::now);
+        input_line += 1;
+        if input.replaced && !replaced {
+            replaced = true;
+            warn(
+                config,
+                "invalid-utf8",
+                name,
+                None,
+                "warning: invalid UTF-8 replaced with U+FFFD",
+            );
+        }
+
+        // In count modes the number is the input position where the line starts
+        // (skipped blank lines are ASCII, so they count the same as bytes or chars)
+        let line_start = match config.count_mode {
+            CountMode::Bytes | CountMode::Chars => position + input.leading_len as i64,
+            CountMode::Lines | CountMode::Words => position,
+        };
+        let line_offset = byte_offset + input.leading_len as i64;
+        byte_offset += input.raw_len as i64;
+        position += match config.count_mode {
+            CountMode::Lines => 0,
+            CountMode::Bytes => input.raw_len as i64,
+            CountMode::Chars => (input.text.chars().count() + input.terminator_len) as i64,
+            CountMode::Words => input.text.split_whitespace().count() as i64,
+        };
+        let ending = record_ending(input.terminator_len, config);
+        let line = input_text(input.text, config);
+        if config.warn_invisible && has_only_invisible(&line) {
+            warn(
+                config,
+                "invisible",
+                name,
+                Some(input_line),
+                "warning: line of only invisible characters",
+            );
+        }
+        if let Some(field) = &numbered
+            && field.is_numbered(&line, config)
+        {
+            write!(out, "{line}{ending}")?;
+            continue;
+        }
+
+        // Squeeze runs of blank lines before anything else sees them (-s of cat)
+        if config.squeeze_blank {
+            if line.is_empty() && prev_blank {
+                continue;
+            }
+            prev_blank = line.is_empty();
+        }
+
+        if let Some(stamp) = &stamp {
+            write!(out, "{stamp} ")?;
+        }
+
+        // A delimiter selects a section by how often the pair repeats
+        let delimiter = delimiter_repeats(line.as_bytes(), delimiter_pair.as_bytes())
+            .filter(|_| config.sections && config.indent_sections.is_none())
+            .and_then(|n| section_for_repeats(n, config));
+        if let Some(section) = delimiter {
+            current_section = section;
+            trace(
+                config,
+                name,
+                input_line,
+                &format!(
+                    "delimiter starts the {} section",
+                    section_name(section, config)
+                ),
+            );
+            if !config.no_renumber {
+                line_number = config.start_number;
+                trace(
+                    config,
+                    name,
+                    input_line,
+                    &format!("numbering restarts at {line_number}"),
+                );
+            }
             blank_count = 0;
-            writeln!(out)?;
+            in_paragraph = false;
+            continued = false;
+            in_quotes = false;
+            fence = None;
+            in_entry = false;
+            if let Some(scanner) = &mut comment_scanner {
+                scanner.reset();
+            }
+            match &config.section_banner {
+                Some(format) => {
+                    if let Some(label) = section_label(section, config) {
+                        write!(out, "{label}")?;
+                    }
+                    let banner = section_banner(format, section_name(section, config));
+                    write!(out, "{banner}{ending}")?;
+                }
+                None => {
+                    if let Some(label) = section_label(section, config) {
+                        write!(out, "{}", label.trim_end())?;
+                    }
+                    out.write_all(ending.as_bytes())?;
+                }
+            }
             continue;
         }
+        // Blank lines stay in the section of the line before them
+        if let Some(unit) = config.indent_sections {
+            if !line.trim().is_empty() {
+                depth = indent_depth(&line, unit);
+                if config.no_renumber {
+                    let len = depth_numbers.len().max(depth + 1);
+                    depth_numbers.resize(len, config.start_number);
+                } else {
+                    depth_numbers.resize(depth + 1, config.start_number);
+                }
+                let section = section_for_depth(depth, config);
+                if config.debug && section != current_section {
+                    let message = format!(
+                        "indentation depth {depth} starts the {} section, numbered from {}",
+                        section_name(section, config),
+                        depth_numbers[depth]
+                    );
+                    trace(config, name, input_line, &message);
+                }
+                current_section = section;
+            }
+            line_number = depth_numbers[depth];
+        }
+        let label = section_label(current_section, config);
+        if let Some(label) = &label {
+            write!(out, "{label}")?;
+        }
 
         let style = match current_section {
             Section::Header => &config.header_style,
             Section::Body => &config.body_style,
             Section::Footer => &config.footer_style,
+            Section::Level(i) => &config.section_levels[i].style,
         };
 
+        let matched = for_matching(&line, config);
+
+        // A continuation belongs to the logical line before it: it is never
+        // numbered, but its content is aligned with that line's content
+        let continuation = (config.paragraphs && in_paragraph && !line.is_empty())
+            || (config.join_continuations && continued)
+            || (config.csv && in_quotes)
+            || (in_entry
+                && config
+                    .log_entries
+                    .as_ref()
+                    .is_some_and(|start| !start.begins_entry(&matched)));
+        if config.log_entries.is_some() {
+            in_entry = true;
+        }
+        if config.paragraphs {
+            in_paragraph = !line.is_empty();
+        }
+        if config.join_continuations {
+            continued = line.ends_with('\\');
+        }
+        // An odd number of quotes leaves a quoted CSV field open; doubled
+        // quotes inside a field cancel out
+        if config.csv && line.matches('"').count() % 2 == 1 {
+            in_quotes = !in_quotes;
+        }
+
+        // Lines left out by the content filters are never numbered
+        let mut excluded = false;
+        if let Some(scanner) = &mut comment_scanner {
+            excluded |= !scanner.has_code(&line);
+        }
+        if let Some(min) = config.min_length {
+            let measured = if config.min_length_trim {
+                line.trim()
+            } else {
+                &line
+            };
+            excluded |= measured.chars().count() < min;
+        }
+        if let Some(mode) = config.markdown {
+            let fence_line = match fence {
+                Some(open) => {
+                    if closes_fence(&line, open) {
+                        fence = None;
+                    }
+                    fence.is_none()
+                }
+                None => {
+                    fence = fence_marker(&line);
+                    fence.is_some()
+                }
+            };
+            let in_code = fence.is_some() && !fence_line;
+            excluded |= match mode {
+                MarkdownMode::SkipCode => in_code || fence_line,
+                MarkdownMode::OnlyCode => !in_code,
+            };
+        }
+        if let Some((start, end)) = &config.between {
+            let marker = if in_region {
+                end.is_match(&matched)
+            } else {
+                start.is_match(&matched)
+            };
+            if marker {
+                in_region = !in_region;
+            }
+            excluded |= marker || !in_region;
+        }
+
         // Handle join_blank (-l): group consecutive blank lines
-        let do_number = if line.is_empty() {
+        let mut do_number = if continuation || excluded || (config.paragraphs && line.is_empty()) {
+            false
+        } else if is_blank(&matched, config) {
             blank_count += 1;
-            if matches!(style, NumberStyle::All) && blank_count >= config.join_blank {
+            let numbers = numbers_blank(&matched, style, ahead.as_ref(), config);
+            if numbers && blank_count >= config.join_blank {
+                if config.join_blank > 1 {
+                    let message = format!(
+                        "blank line numbered, ending a group of {blank_count} (-l {})",
+                        config.join_blank
+                    );
+                    trace(config, name, input_line, &message);
+                }
                 blank_count = 0;
                 true
             } else {
@@ -337,40 +3561,433 @@ fn number_lines(reader: impl Read, config: &Config) -> io::Result<()> {
             }
         } else {
             blank_count = 0;
-            should_number(&line, style)
+            should_number(&matched, style, ahead.as_ref(), config)
+        };
+
+        // With --unique, repeated content keeps the number of its first occurrence
+        let duplicate_of = if config.unique.is_some() && do_number {
+            first_numbers.get(&line).copied()
+        } else {
+            None
         };
+        if duplicate_of.is_some() {
+            do_number = false;
+        }
+        if config.debug && !do_number && config.diff.is_none() {
+            let section = section_name(current_section, config);
+            let reason = if continuation {
+                "it continues the line before".to_string()
+            } else if excluded {
+                "a content filter leaves it out".to_string()
+            } else if config.paragraphs && line.is_empty() {
+                "it separates paragraphs".to_string()
+            } else if duplicate_of.is_some() {
+                "--unique: it repeats an earlier line".to_string()
+            } else if is_blank(&matched, config)
+                && numbers_blank(&matched, style, ahead.as_ref(), config)
+            {
+                format!(
+                    "blank line {blank_count} of a group of {} (-l)",
+                    config.join_blank
+                )
+            } else if is_blank(&matched, config) {
+                format!("the {section} style does not number blank lines")
+            } else {
+                format!("the {section} style does not select it")
+            };
+            trace(
+                config,
+                name,
+                input_line,
+                &format!("line not numbered: {reason}"),
+            );
+        }
 
-        if do_number {
-            let num = format_number(line_number, config.number_width, config.number_format);
-            writeln!(out, "{}{}{}", num, config.separator, line)?;
+        let mut global = None;
+        let number = if let Some(mode) = config.diff {
+            // Diff mode numbers by position in the new file, not by line count
+            let new_line = hunk.as_mut().and_then(|h| h.advance(&line));
+            if hunk.as_ref().is_some_and(|h| h.is_done()) {
+                hunk = None;
+            }
+            if hunk.is_none() {
+                hunk = Hunk::parse(&line);
+            }
+            let wanted = match mode {
+                DiffMode::All => true,
+                DiffMode::Added => line.starts_with('+'),
+            };
+            new_line.filter(|_| wanted && !excluded)
+        } else if do_number {
+            let number = match config.count_mode {
+                CountMode::Lines => line_number,
+                _ => line_start,
+            };
             line_number += config.increment;
+            if config.indent_sections.is_some() {
+                depth_numbers[depth] = line_number;
+            }
+            global = Some(global_number);
+            global_number += config.increment;
+            Some(number)
         } else {
-            // Print empty prefix to align with numbered lines
-            writeln!(out, "{}{}", " ".repeat(config.number_width), line)?;
+            None
+        };
+
+        if config.unique.is_some()
+            && let Some(n) = number
+        {
+            remembered += line.len();
+            if let Some(limit) = config.max_memory
+                && remembered > limit
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::OutOfMemory,
+                    format!("--unique needs more than --max-memory ({limit} bytes)"),
+                ));
+            }
+            first_numbers.insert(line.clone(), n);
+        }
+        // The gutter of an annotated duplicate points back to the original
+        let annotation = duplicate_of.filter(|_| config.unique == Some(UniqueMode::Annotate));
+        if let Some(n) = number {
+            tally.numbered += 1;
+            tally.first.get_or_insert(n);
+            tally.last = Some(n);
+            match current_section {
+                Section::Header => tally.header += 1,
+                Section::Body => tally.body += 1,
+                Section::Footer => tally.footer += 1,
+                Section::Level(_) => {}
+            }
+        }
+        for n in [number, global].into_iter().flatten() {
+            tally.widest = tally.widest.max(number_len(n, config));
+        }
+
+        let content = render_content(&line, config);
+        let start_column = stamp.as_ref().map_or(0, |s| width::str_width(s) + 1)
+            + label.as_deref().map_or(0, width::str_width);
+        if let Some(margin) = config.right_margin
+            && config.columns.is_none()
+        {
+            let number = match annotation {
+                Some(original) => Some(with_global(
+                    duplicate_gutter(original, config),
+                    None,
+                    config,
+                )),
+                None => number.map(|n| with_global(gutter(Some(n), config), global, config)),
+            };
+            write_right_aligned(
+                out,
+                start_column,
+                &content,
+                number.as_deref(),
+                margin,
+                ending,
+                tab_width(config),
+            )?;
+            continue;
         }
+        // Everything written before the content on the first output row
+        let (leader, content) = if let Some(columns) = &config.columns {
+            let mut fields: Vec<Cow<str>> = columns
+                .iter()
+                .map(|column| match column {
+                    Column::Number => Cow::Owned(match annotation {
+                        Some(original) => duplicate_gutter(original, config),
+                        None if number.is_none() => unnumbered_gutter(&line, config),
+                        None => gutter(number, config),
+                    }),
+                    Column::Global => Cow::Owned(gutter(global, config)),
+                    Column::Offset => Cow::Owned(
+                        config
+                            .number_format
+                            .format(line_offset, config.number_width),
+                    ),
+                    Column::Section => Cow::Borrowed(section_name(current_section, config)),
+                    Column::Filename => Cow::Borrowed(name),
+                    Column::Length => Cow::Owned(
+                        config
+                            .number_format
+                            .format(line.chars().count() as i64, config.number_width),
+                    ),
+                    Column::Percent => Cow::Owned(percent(byte_offset, total)),
+                    Column::Text => Cow::Borrowed(&*content),
+                })
+                .collect();
+            // Only a trailing text column can be wrapped
+            if columns.last() == Some(&Column::Text) {
+                fields.pop();
+                fields.push(Cow::Borrowed(""));
+                (fields.join(config.column_separator.as_str()), content)
+            } else {
+                (
+                    fields.join(config.column_separator.as_str()),
+                    Cow::Borrowed(""),
+                )
+            }
+        } else if number.is_some() {
+            (
+                with_global(gutter(number, config), global, config) + &config.separator,
+                content,
+            )
+        } else if let Some(original) = annotation {
+            (
+                with_global(duplicate_gutter(original, config), None, config) + &config.separator,
+                content,
+            )
+        } else if line.is_empty() && config.blank_placeholder.is_some() {
+            (
+                with_global(unnumbered_gutter(&line, config), None, config),
+                content,
+            )
+        } else if continuation {
+            (continuation_leader(config), content)
+        } else if config.pad_unnumbered {
+            // Print empty prefix to align with numbered lines
+            (with_global(gutter(None, config), None, config), content)
+        } else {
+            (String::new(), content)
+        };
+        write_row(out, start_column, &leader, &content, ending, config)?;
     }
 
-    Ok(())
+    tally.carry = Some(Carry {
+        number: line_number,
+        section: current_section,
+        blank_count,
+    });
+    Ok(tally)
+}
+
+/// The `--report` JSON for a run over `paths` that came to `results`, and
+/// failed writing output with `output_error` if set.
+fn run_report(
+    paths: &[String],
+    results: &[io::Result<Tally>],
+    output_error: Option<&io::Error>,
+    elapsed: Duration,
+) -> String {
+    fn with_counts(object: JsonObject, tally: &Tally) -> JsonObject {
+        let sections = JsonObject::new()
+            .raw("header", tally.header)
+            .raw("body", tally.body)
+            .raw("footer", tally.footer)
+            .finish();
+        object
+            .raw("numbered", tally.numbered)
+            .raw("sections", sections)
+            .nullable("first", tally.first)
+            .nullable("last", tally.last)
+    }
+
+    let mut files = Vec::new();
+    let mut total = Tally::default();
+    let mut error = output_error.map(diagnostic::error_json);
+    for (path, result) in paths.iter().zip(results) {
+        let file = JsonObject::new().string("path", path);
+        files.push(match result {
+            Ok(tally) => {
+                total.add(*tally);
+                let file = file.string("status", status(result));
+                with_counts(file, tally)
+                    .raw("seconds", format!("{:.6}", tally.elapsed.as_secs_f64()))
+                    .raw("error", "null")
+                    .finish()
+            }
+            Err(e) => {
+                let json = diagnostic::error_json(e);
+                error = Some(json.clone());
+                file.string("status", "failed").raw("error", json).finish()
+            }
+        });
+    }
+    let run = JsonObject::new().raw("files", format!("[{}]", files.join(",")));
+    with_counts(run, &total)
+        .raw("warnings", WARNINGS.load(Ordering::Relaxed))
+        .nullable("error", error)
+        .raw("seconds", format!("{:.6}", elapsed.as_secs_f64()))
+        .finish()
+        + "\n"
+}
+
+/// Whether `e` means the reader of our output went away (`nl ... | head`),
+/// which is not worth reporting. Windows reports a pipe closed by the
+/// consumer under several error codes, not all of which std maps to
+/// `BrokenPipe`.
+fn is_broken_pipe(e: &io::Error) -> bool {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        return true;
+    }
+    // ERROR_BROKEN_PIPE, ERROR_NO_DATA ("The pipe is being closed"),
+    // ERROR_PIPE_NOT_CONNECTED
+    cfg!(windows) && matches!(e.raw_os_error(), Some(109 | 232 | 233))
+}
+
+/// `--check-args`: open each input read-only, reporting those that cannot
+/// be. The options have been checked by the time this runs. Returns the
+/// exit status.
+fn check_inputs(paths: &[String]) -> i32 {
+    let mut status = 0;
+    for path in paths.iter().filter(|path| *path != "-") {
+        if let Err(e) = File::open(path) {
+            diagnostic::report_error(&diagnostic::with_path(e, path));
+            status = 1;
+        }
+    }
+    status
 }
 
 fn main() {
     let config = parse_args();
+    interrupt::install();
 
-    let result = match &config.file {
-        Some(path) => match File::open(path) {
-            Ok(file) => number_lines(file, &config),
+    let stdin = ["-".to_string()];
+    let paths = if config.files.is_empty() {
+        &stdin[..]
+    } else {
+        &config.files
+    };
+    if config.check_args {
+        process::exit(check_inputs(paths));
+    }
+    let copy = config.tee.as_ref().map(|path| {
+        let opened = if config.append {
+            fs::OpenOptions::new().append(true).create(true).open(path)
+        } else {
+            File::create(path)
+        };
+        match opened {
+            Ok(file) => file,
             Err(e) => {
-                eprintln!("nl: {path}: {e}");
+                diagnostic::report_error(&diagnostic::with_path(e, path));
                 process::exit(1);
             }
-        },
-        None => number_lines(io::stdin(), &config),
+        }
+    });
+    let stdout = pager::Output::new(config.pager.clone(), terminal::height());
+    let mut out = WatchedWriter {
+        inner: io::BufWriter::new(tee::Tee::new(stdout, copy)),
+        failed: false,
     };
+    let started = Instant::now();
+    let results = number_files(paths, &mut out, &config);
+    // A write that failed has already been reported with its input
+    let output_failed = out.failed;
+    let numbered: Vec<(&String, Tally)> = paths
+        .iter()
+        .zip(&results)
+        .filter_map(|(path, result)| Some((path, *result.as_ref().ok()?)))
+        .collect();
+    let summarized = if output_failed {
+        Ok(())
+    } else {
+        write_summary(&mut out, &numbered, &config)
+    };
+    let flushed = out.flush();
+    let finished = match out.inner.into_inner() {
+        Ok(out) => out.finish().and_then(pager::Output::finish),
+        Err(e) => Err(e.into_error()),
+    };
+    let output = summarized.and(flushed).and(finished);
+    let output_error = output.as_ref().err().filter(|_| !output_failed);
 
-    if let Err(e) = result {
-        if e.kind() != io::ErrorKind::BrokenPipe {
-            eprintln!("nl: {e}");
+    if let Some(report) = &config.report {
+        let json = run_report(paths, &results, output_error, started.elapsed());
+        if let Err(e) = fs::write(report, json) {
+            diagnostic::report_error(&diagnostic::with_path(e, report));
             process::exit(1);
         }
     }
+
+    // Output has been flushed by the time processing unwinds
+    if interrupt::requested() {
+        process::exit(interrupt::EXIT_STATUS);
+    }
+    let mut failed = 0;
+    for e in results.iter().filter_map(|r| r.as_ref().err()) {
+        if !is_broken_pipe(e) {
+            diagnostic::report_error(e);
+            failed += 1;
+        }
+    }
+    if let Some(e) = output_error
+        && !is_broken_pipe(e)
+    {
+        diagnostic::report_error(e);
+        process::exit(1);
+    }
+    if !output_failed
+        && !config.quiet
+        && let Some(line) = status_line(paths.len(), &results)
+    {
+        diagnostic::report("status", None, None, &line);
+    }
+    // A run of several inputs that all failed gets its own status; a
+    // single unreadable input keeps status 1
+    if paths.len() > 1 && failed == paths.len() && !output_failed {
+        process::exit(2);
+    }
+    if failed > 0 {
+        process::exit(1);
+    }
+    if WARNINGS.load(Ordering::Relaxed) > 0 {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every record `separator` splits `input` into, as text and raw
+    /// length, reading through a buffer of `capacity` bytes.
+    fn records(input: &[u8], separator: RecordSeparator, capacity: usize) -> Vec<(String, usize)> {
+        let mut buf = BufReader::with_capacity(capacity, input);
+        let mut bytes = Vec::new();
+        let mut records = Vec::new();
+        while let Some(record) = read_record(&mut buf, &mut bytes, &separator, None, false).unwrap()
+        {
+            records.push((record.text, record.raw_len));
+        }
+        records
+    }
+
+    #[test]
+    fn delimiter_split_across_reads_still_ends_a_record() {
+        let separator = RecordSeparator::Delimiter(b"::".to_vec());
+        for capacity in [1, 2, 3, 64] {
+            assert_eq!(
+                records(b"a:b::c::d", separator.clone(), capacity),
+                [("a:b".into(), 5), ("c".into(), 3), ("d".into(), 1)]
+            );
+        }
+    }
+
+    #[test]
+    fn paragraphs_end_at_runs_of_blank_lines() {
+        let input = b"\n\none\ntwo\n\n\n\nthree\n";
+        assert_eq!(
+            records(input, RecordSeparator::Paragraph, 4),
+            [("one\ntwo".into(), 13), ("three".into(), 6)]
+        );
+    }
+
+    #[test]
+    fn last_record_may_lack_its_terminator() {
+        assert_eq!(
+            records(b"a\nb", RecordSeparator::Newline, 64),
+            [("a".into(), 2), ("b".into(), 1)]
+        );
+        assert_eq!(
+            records(b"one\n\ntwo", RecordSeparator::Paragraph, 64),
+            [("one".into(), 5), ("two".into(), 3)]
+        );
+        assert_eq!(
+            records(b"a;b", RecordSeparator::Delimiter(b";".to_vec()), 64),
+            [("a".into(), 2), ("b".into(), 1)]
+        );
+    }
 }
@@ -0,0 +1,69 @@
+//! Stand-in for the `regex` crate in builds without the `regex` feature.
+//! No pattern can be compiled, so no `Regex` is ever made and nothing
+//! ever matches.
+
+use std::fmt;
+use std::ops::Index;
+
+#[derive(Clone, Debug)]
+pub struct Regex(());
+
+pub struct Match(());
+
+pub struct Captures(());
+
+/// Why a pattern could not be compiled.
+#[derive(Debug)]
+pub struct Unsupported;
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "this nl was built without the regex feature")
+    }
+}
+
+impl Regex {
+    pub fn new(_: &str) -> Result<Regex, Unsupported> {
+        Err(Unsupported)
+    }
+
+    pub fn is_match(&self, _: &str) -> bool {
+        false
+    }
+
+    pub fn find(&self, _: &str) -> Option<Match> {
+        None
+    }
+
+    pub fn captures(&self, _: &str) -> Option<Captures> {
+        None
+    }
+}
+
+impl Match {
+    pub fn start(&self) -> usize {
+        0
+    }
+
+    pub fn end(&self) -> usize {
+        0
+    }
+}
+
+impl Captures {
+    pub fn get(&self, _: usize) -> Option<Match> {
+        None
+    }
+}
+
+impl Index<usize> for Captures {
+    type Output = str;
+
+    fn index(&self, _: usize) -> &str {
+        ""
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    text.to_string()
+}
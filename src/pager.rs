@@ -0,0 +1,117 @@
+use std::io::{self, IsTerminal, Stdout, Write};
+use std::process::{Child, Command, Stdio};
+
+/// Pager used when neither `--pager=CMD` nor `NL_PAGER` names one.
+pub const DEFAULT_COMMAND: &str = "less -R";
+
+enum State {
+    Holding(Vec<u8>), // output so far, while it still fits on one screen
+    Paging(Child),    // output goes to the pager's stdin
+    Direct,           // output goes straight to stdout
+}
+
+/// Standard output that is handed to a pager once it grows past a
+/// screenful. Until then it is held back, so short output is written
+/// directly and never starts one. Not being a terminal, or a pager that
+/// cannot be started, means plain stdout.
+pub struct Output {
+    stdout: Stdout,
+    command: Option<String>,
+    rows: usize,
+    held_lines: usize,
+    state: State,
+}
+
+impl Output {
+    /// Output paged through `command` (split on whitespace), or not paged
+    /// at all if `None`.
+    pub fn new(command: Option<String>, rows: usize) -> Self {
+        let stdout = io::stdout();
+        let state = if command.is_some() && stdout.is_terminal() {
+            State::Holding(Vec::new())
+        } else {
+            State::Direct
+        };
+        Output {
+            stdout,
+            command,
+            rows,
+            held_lines: 0,
+            state,
+        }
+    }
+
+    /// Start the pager, giving it the output held so far. Falls back to
+    /// stdout if it cannot be run.
+    fn start(&mut self, held: Vec<u8>) -> io::Result<()> {
+        let mut words = self.command.as_deref().unwrap_or("").split_whitespace();
+        let child = match words.next() {
+            Some(program) => Command::new(program)
+                .args(words)
+                .stdin(Stdio::piped())
+                .spawn()
+                .ok(),
+            None => None,
+        };
+        match child {
+            Some(mut child) => {
+                let written = child.stdin.as_mut().map_or(Ok(()), |s| s.write_all(&held));
+                self.state = State::Paging(child);
+                written
+            }
+            None => {
+                self.state = State::Direct;
+                self.stdout.write_all(&held)
+            }
+        }
+    }
+
+    /// Write out anything still held back and wait for the pager to exit.
+    pub fn finish(mut self) -> io::Result<()> {
+        match std::mem::replace(&mut self.state, State::Direct) {
+            State::Holding(held) => {
+                self.stdout.write_all(&held)?;
+                self.stdout.flush()
+            }
+            State::Paging(mut child) => {
+                // Closing its stdin tells the pager the output is complete
+                drop(child.stdin.take());
+                child.wait()?;
+                Ok(())
+            }
+            State::Direct => self.stdout.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.state {
+            State::Holding(held) => {
+                held.extend_from_slice(buf);
+                self.held_lines += buf.iter().filter(|&&b| b == b'\n').count();
+                if self.held_lines >= self.rows {
+                    let held = std::mem::take(held);
+                    self.start(held)?;
+                }
+                Ok(buf.len())
+            }
+            State::Paging(child) => match &mut child.stdin {
+                Some(stdin) => stdin.write(buf),
+                None => Ok(buf.len()),
+            },
+            State::Direct => self.stdout.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.state {
+            State::Holding(_) => Ok(()),
+            State::Paging(child) => match &mut child.stdin {
+                Some(stdin) => stdin.flush(),
+                None => Ok(()),
+            },
+            State::Direct => self.stdout.flush(),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{Numbering, Section, Style};
+
+/// What a [`NumberingPolicy`] does with a line.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Decision {
+    Skip,        // leave the line unnumbered
+    Next,        // give it the next number
+    Number(i64), // give it this number, and count on from it
+}
+
+/// Decides which lines get a number, for rules the styles cannot express.
+pub trait NumberingPolicy {
+    /// Decide on `line`, the input line at `index` (counting from 0,
+    /// delimiter lines included), which is in `section`. `line` is passed
+    /// without its terminator.
+    fn decide(&mut self, line: &[u8], index: u64, section: Section) -> Decision;
+}
+
+impl Style {
+    /// Whether the style numbers `line`. A `p` regex sees the line with
+    /// invalid UTF-8 replaced.
+    fn numbers(&self, line: &[u8]) -> bool {
+        match self {
+            Style::All => true,
+            Style::NonEmpty => !line.is_empty(),
+            Style::None => false,
+            Style::Pattern(re) => re.is_match(&String::from_utf8_lossy(line)),
+            Style::And(styles) => styles.iter().all(|style| style.numbers(line)),
+            Style::Or(styles) => styles.iter().any(|style| style.numbers(line)),
+            Style::Not(style) => !style.numbers(line),
+        }
+    }
+}
+
+/// A style numbers the same way in every section.
+impl NumberingPolicy for Style {
+    fn decide(&mut self, line: &[u8], _: u64, _: Section) -> Decision {
+        if self.numbers(line) {
+            Decision::Next
+        } else {
+            Decision::Skip
+        }
+    }
+}
+
+/// A style for each section, like `-h`, `-b` and `-f`. The policy used
+/// unless another is given. Further levels of section, which only a
+/// custom classifier tells apart, are numbered like the body.
+#[derive(Clone, Debug)]
+pub struct SectionStyles {
+    pub header: Style,
+    pub body: Style,
+    pub footer: Style,
+}
+
+impl From<&Numbering> for SectionStyles {
+    fn from(numbering: &Numbering) -> Self {
+        SectionStyles {
+            header: numbering.header.clone(),
+            body: numbering.body.clone(),
+            footer: numbering.footer.clone(),
+        }
+    }
+}
+
+impl NumberingPolicy for SectionStyles {
+    fn decide(&mut self, line: &[u8], index: u64, section: Section) -> Decision {
+        let style = match section {
+            Section::Header => &mut self.header,
+            Section::Body | Section::Level(_) => &mut self.body,
+            Section::Footer => &mut self.footer,
+        };
+        style.decide(line, index, section)
+    }
+}
@@ -0,0 +1,108 @@
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Minimum time between redraws of the progress line.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Width of the bar drawn when the total size is known.
+const BAR_WIDTH: usize = 30;
+
+struct Meter {
+    total: Option<u64>,
+    read: u64,
+    start: Instant,
+    last_draw: Option<Instant>,
+    drawn_len: usize,
+}
+
+/// Reader adapter that renders a progress line on stderr as input is read:
+/// a bar and percentage when the total size is known, throughput otherwise.
+/// Passes data through untouched; without a meter it does nothing else.
+pub struct ProgressReader<R> {
+    inner: R,
+    meter: Option<Meter>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    /// `total` is `None` to disable the meter, `Some(None)` for input of
+    /// unknown size (pipes), and `Some(Some(len))` for files.
+    pub fn new(inner: R, total: Option<Option<u64>>) -> Self {
+        ProgressReader {
+            inner,
+            meter: total.map(|total| Meter {
+                total,
+                read: 0,
+                start: Instant::now(),
+                last_draw: None,
+                drawn_len: 0,
+            }),
+        }
+    }
+}
+
+fn human_bytes(n: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = n;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{value:.0} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+impl Meter {
+    fn draw(&mut self) {
+        let elapsed = self.start.elapsed().as_secs_f64().max(1e-3);
+        let rate = human_bytes(self.read as f64 / elapsed);
+        let line = match self.total {
+            Some(total) if total > 0 => {
+                let fraction = (self.read as f64 / total as f64).min(1.0);
+                let filled = (fraction * BAR_WIDTH as f64) as usize;
+                format!(
+                    "[{}{}] {:3.0}% {} / {} ({rate}/s)",
+                    "=".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled),
+                    fraction * 100.0,
+                    human_bytes(self.read as f64),
+                    human_bytes(total as f64),
+                )
+            }
+            _ => format!("{} read ({rate}/s)", human_bytes(self.read as f64)),
+        };
+        let pad = self.drawn_len.saturating_sub(line.len());
+        let _ = write!(io::stderr(), "\r{line}{}", " ".repeat(pad));
+        self.drawn_len = line.len();
+        self.last_draw = Some(Instant::now());
+    }
+
+    /// Erase the progress line so it does not mix with later messages.
+    fn clear(&mut self) {
+        if self.drawn_len > 0 {
+            let _ = write!(io::stderr(), "\r{}\r", " ".repeat(self.drawn_len));
+            self.drawn_len = 0;
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(meter) = &mut self.meter {
+            meter.read += n as u64;
+            if n == 0 {
+                meter.clear();
+            } else if meter
+                .last_draw
+                .is_none_or(|at| at.elapsed() >= REDRAW_INTERVAL)
+            {
+                meter.draw();
+            }
+        }
+        Ok(n)
+    }
+}
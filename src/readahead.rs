@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Open `path` for one front-to-back pass, telling the OS so it can read
+/// ahead aggressively: `FILE_FLAG_SEQUENTIAL_SCAN` on Windows,
+/// `POSIX_FADV_SEQUENTIAL` on Linux. The hint is best effort.
+pub fn open_sequential(path: &Path) -> io::Result<File> {
+    let file = open(path)?;
+    advise(&file, Advice::Sequential);
+    Ok(file)
+}
+
+/// Tell the OS the cached pages of `file` will not be needed again, so bulk
+/// numbering does not push more useful data out of the page cache. Only
+/// has an effect on Linux.
+pub fn drop_cached(file: &File) {
+    advise(file, Advice::DontNeed);
+}
+
+#[cfg(windows)]
+fn open(path: &Path) -> io::Result<File> {
+    use std::fs::OpenOptions;
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_FLAG_SEQUENTIAL_SCAN: u32 = 0x0800_0000;
+
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(FILE_FLAG_SEQUENTIAL_SCAN)
+        .open(path)
+}
+
+#[cfg(not(windows))]
+fn open(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+enum Advice {
+    Sequential, // POSIX_FADV_SEQUENTIAL
+    DontNeed,   // POSIX_FADV_DONTNEED
+}
+
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+fn advise(file: &File, advice: Advice) {
+    use std::ffi::c_int;
+    use std::os::fd::AsRawFd;
+
+    unsafe extern "C" {
+        fn posix_fadvise(fd: c_int, offset: i64, len: i64, advice: c_int) -> c_int;
+    }
+
+    let advice = match advice {
+        Advice::Sequential => 2,
+        Advice::DontNeed => 4,
+    };
+    // SAFETY: the descriptor is open for the duration of the call; a length
+    // of 0 covers the whole file
+    unsafe {
+        posix_fadvise(file.as_raw_fd(), 0, 0, advice);
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+fn advise(_: &File, _: Advice) {}
@@ -0,0 +1,92 @@
+use crate::{
+    NumberFormatter, NumberedLine, Numberer, Numbering, NumberingPolicy, SectionClassifier,
+    SectionStyles,
+};
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Reader that yields the lines of `inner` numbered, for code that takes
+/// its input as an `io::Read`. Lines are numbered one at a time as they
+/// are read.
+pub struct LineNumberingReader<R: Read> {
+    inner: BufReader<R>,
+    numberer: Numberer,
+    line: Vec<u8>,     // the input line being numbered
+    numbered: Vec<u8>, // the numbered line, to be read out
+    pos: usize,        // how much of `numbered` has been read
+}
+
+impl<R: Read> LineNumberingReader<R> {
+    /// Number with the styles in `numbering`.
+    pub fn new(inner: R, numbering: Numbering) -> Self {
+        let policy = SectionStyles::from(&numbering);
+        LineNumberingReader::with_policy(inner, numbering, policy)
+    }
+
+    /// Number the lines `policy` picks, laid out as `numbering` says (its
+    /// styles go unused).
+    pub fn with_policy(
+        inner: R,
+        numbering: Numbering,
+        policy: impl NumberingPolicy + Send + 'static,
+    ) -> Self {
+        LineNumberingReader {
+            inner: BufReader::new(inner),
+            numberer: Numberer::new(numbering, Box::new(policy)),
+            line: Vec::new(),
+            numbered: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Tell sections apart with `classifier` rather than by delimiter
+    /// lines.
+    pub fn set_classifier(&mut self, classifier: impl SectionClassifier + Send + 'static) {
+        self.numberer.classifier = Box::new(classifier);
+    }
+
+    /// Render numbers with `formatter` rather than the `Numbering` format.
+    pub fn set_formatter(&mut self, formatter: impl NumberFormatter + Send + 'static) {
+        self.numberer.formatter = Box::new(formatter);
+    }
+
+    /// Call `hook` with each line as it is numbered, to collect line
+    /// numbers or report progress alongside the output.
+    pub fn on_line(&mut self, hook: impl FnMut(&NumberedLine) + Send + 'static) {
+        self.numberer.observer = Some(Box::new(hook));
+    }
+
+    /// Number the next input line into `numbered`. Returns false at the
+    /// end of the input.
+    fn next_line(&mut self) -> io::Result<bool> {
+        self.numbered.clear();
+        self.pos = 0;
+        // A delimiter without a line end gives no output; go on past it
+        while self.numbered.is_empty() {
+            self.line.clear();
+            if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(false);
+            }
+            let ended = self.line.last() == Some(&b'\n');
+            let text = &self.line[..self.line.len() - usize::from(ended)];
+            self.numberer
+                .write_line(text, self.line.len(), &mut self.numbered)?;
+            if ended {
+                self.numbered.push(b'\n');
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for LineNumberingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.numbered.len() && !self.next_line()? {
+            return Ok(0);
+        }
+        let pending = &self.numbered[self.pos..];
+        let n = pending.len().min(buf.len());
+        buf[..n].copy_from_slice(&pending[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
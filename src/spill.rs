@@ -0,0 +1,95 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Distinguishes the temporary files of one process.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+struct TempFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+/// Output buffer that keeps its contents in memory up to `limit` bytes and
+/// moves them to a temporary file beyond that. Without a limit it is a
+/// plain `Vec`. The file is removed when the buffer is dropped.
+pub struct SpillBuffer {
+    memory: Vec<u8>,
+    spilled: Option<TempFile>,
+    limit: Option<usize>,
+}
+
+impl SpillBuffer {
+    pub fn new(limit: Option<usize>) -> Self {
+        SpillBuffer {
+            memory: Vec::new(),
+            spilled: None,
+            limit,
+        }
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("nl-{}-{id}.tmp", process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let mut spilled = TempFile {
+            path,
+            file: BufWriter::new(file),
+        };
+        spilled.file.write_all(&self.memory)?;
+        self.memory = Vec::new();
+        self.spilled = Some(spilled);
+        Ok(())
+    }
+
+    /// Write everything buffered to `out`.
+    pub fn copy_to(mut self, out: &mut impl Write) -> io::Result<()> {
+        match &mut self.spilled {
+            Some(spilled) => {
+                spilled.file.flush()?;
+                let file = spilled.file.get_mut();
+                file.seek(SeekFrom::Start(0))?;
+                io::copy(file, out)?;
+                Ok(())
+            }
+            None => out.write_all(&self.memory),
+        }
+    }
+}
+
+impl Write for SpillBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(spilled) = &mut self.spilled {
+            return spilled.file.write(buf);
+        }
+        self.memory.extend_from_slice(buf);
+        if self.limit.is_some_and(|limit| self.memory.len() > limit) {
+            self.spill()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.spilled {
+            Some(spilled) => spilled.file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SpillBuffer {
+    fn drop(&mut self) {
+        // Windows cannot remove a file that is still open
+        if let Some(TempFile { path, file }) = self.spilled.take() {
+            drop(file);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
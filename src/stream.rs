@@ -0,0 +1,89 @@
+use crate::{NumberedLine, Numberer, Numbering, NumberingPolicy, SectionClassifier, SectionStyles};
+use std::borrow::Cow;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// The lines of `reader` as [`NumberedLine`]s, numbered as `numbering`
+/// says, for programs that want the numbers rather than numbered text.
+pub fn stream<R: Read>(reader: R, numbering: Numbering) -> Stream<R> {
+    let policy = SectionStyles::from(&numbering);
+    Stream::with_policy(reader, numbering, policy)
+}
+
+/// Iterator over the numbered lines of a reader; see [`stream`].
+/// Delimiter lines are left out. A read error ends the iteration after it
+/// is yielded.
+pub struct Stream<R: Read> {
+    inner: BufReader<R>,
+    numberer: Numberer,
+    line: Vec<u8>, // the input line being numbered
+    failed: bool,
+}
+
+impl<R: Read> Stream<R> {
+    /// Number the lines `policy` picks (the styles in `numbering` go
+    /// unused).
+    pub fn with_policy(
+        inner: R,
+        numbering: Numbering,
+        policy: impl NumberingPolicy + Send + 'static,
+    ) -> Self {
+        Stream {
+            inner: BufReader::new(inner),
+            numberer: Numberer::new(numbering, Box::new(policy)),
+            line: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// Tell sections apart with `classifier` rather than by delimiter
+    /// lines.
+    pub fn set_classifier(&mut self, classifier: impl SectionClassifier + Send + 'static) {
+        self.numberer.classifier = Box::new(classifier);
+    }
+
+    /// The next numbered line, with its text borrowed from a buffer that is
+    /// reused for every line, so reading needs no allocation per line. The
+    /// iterator interface copies each line out instead.
+    pub fn next_line(&mut self) -> Option<io::Result<NumberedLine<'_>>> {
+        if self.failed {
+            return None;
+        }
+        let numbered = loop {
+            self.line.clear();
+            match self.inner.read_until(b'\n', &mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+            let text = &self.line[..self.text_len()];
+            // Keep only the numbering, so the buffer is free to reuse
+            if let Some(line) = self.numberer.number(text, self.line.len()) {
+                break NumberedLine {
+                    text: Cow::Borrowed(&[]),
+                    ..line
+                };
+            }
+        };
+        Some(Ok(NumberedLine {
+            text: Cow::Borrowed(&self.line[..self.text_len()]),
+            ..numbered
+        }))
+    }
+
+    /// Length of the line in the buffer without its terminator.
+    fn text_len(&self) -> usize {
+        self.line.len() - usize::from(self.line.last() == Some(&b'\n'))
+    }
+}
+
+impl<R: Read> Iterator for Stream<R> {
+    type Item = io::Result<NumberedLine<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_line()
+            .map(|line| line.map(NumberedLine::into_owned))
+    }
+}
@@ -0,0 +1,45 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Writer that passes everything on to `inner` and, if set, also to a copy
+/// file, like tee(1).
+pub struct Tee<W> {
+    inner: W,
+    copy: Option<BufWriter<File>>,
+}
+
+impl<W: Write> Tee<W> {
+    pub fn new(inner: W, copy: Option<File>) -> Self {
+        Tee {
+            inner,
+            copy: copy.map(BufWriter::new),
+        }
+    }
+
+    /// Flush the copy to its file and return the inner writer.
+    pub fn finish(self) -> io::Result<W> {
+        if let Some(mut copy) = self.copy {
+            copy.flush()?;
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Tee<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Write all of it to both, so they cannot get out of step
+        self.inner.write_all(buf)?;
+        if let Some(copy) = &mut self.copy {
+            copy.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        if let Some(copy) = &mut self.copy {
+            copy.flush()?;
+        }
+        Ok(())
+    }
+}
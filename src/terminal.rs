@@ -0,0 +1,112 @@
+use std::env;
+
+/// Fallback width when stdout is not a terminal and `COLUMNS` is unset.
+const DEFAULT_WIDTH: usize = 80;
+
+/// Fallback height when stdout is not a terminal and `LINES` is unset.
+const DEFAULT_HEIGHT: usize = 24;
+
+/// Width of the terminal in columns: the size of the console attached to
+/// stdout, else `$COLUMNS`, else 80.
+pub fn width() -> usize {
+    console_size()
+        .map(|(cols, _)| cols)
+        .or_else(|| env::var("COLUMNS").ok()?.parse().ok())
+        .filter(|&w| w > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Height of the terminal in rows: the size of the console attached to
+/// stdout, else `$LINES`, else 24.
+pub fn height() -> usize {
+    console_size()
+        .map(|(_, rows)| rows)
+        .or_else(|| env::var("LINES").ok()?.parse().ok())
+        .filter(|&h| h > 0)
+        .unwrap_or(DEFAULT_HEIGHT)
+}
+
+/// Columns and rows of the console attached to stdout.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn console_size() -> Option<(usize, usize)> {
+    #[repr(C)]
+    struct WinSize {
+        rows: u16,
+        cols: u16,
+        x_pixels: u16,
+        y_pixels: u16,
+    }
+
+    #[cfg(target_os = "linux")]
+    const TIOCGWINSZ: std::ffi::c_ulong = 0x5413;
+    #[cfg(target_os = "macos")]
+    const TIOCGWINSZ: std::ffi::c_ulong = 0x4008_7468;
+
+    unsafe extern "C" {
+        fn ioctl(fd: std::ffi::c_int, request: std::ffi::c_ulong, ...) -> std::ffi::c_int;
+    }
+
+    let mut size = WinSize {
+        rows: 0,
+        cols: 0,
+        x_pixels: 0,
+        y_pixels: 0,
+    };
+    // SAFETY: TIOCGWINSZ writes a `struct winsize` through the pointer
+    let rc = unsafe { ioctl(1, TIOCGWINSZ, &mut size as *mut WinSize) };
+    (rc == 0 && size.cols > 0).then_some((usize::from(size.cols), usize::from(size.rows)))
+}
+
+/// Columns and rows of the console attached to stdout.
+#[cfg(windows)]
+fn console_size() -> Option<(usize, usize)> {
+    #[repr(C)]
+    struct Coord {
+        x: i16,
+        y: i16,
+    }
+
+    #[repr(C)]
+    struct SmallRect {
+        left: i16,
+        top: i16,
+        right: i16,
+        bottom: i16,
+    }
+
+    #[repr(C)]
+    struct ScreenBufferInfo {
+        size: Coord,
+        cursor_position: Coord,
+        attributes: u16,
+        window: SmallRect,
+        maximum_window_size: Coord,
+    }
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut std::ffi::c_void;
+        fn GetConsoleScreenBufferInfo(
+            console: *mut std::ffi::c_void,
+            info: *mut ScreenBufferInfo,
+        ) -> i32;
+    }
+
+    // SAFETY: plain Win32 calls; `info` is a correctly laid out out-parameter
+    unsafe {
+        let mut info: ScreenBufferInfo = std::mem::zeroed();
+        if GetConsoleScreenBufferInfo(GetStdHandle(STD_OUTPUT_HANDLE), &mut info) == 0 {
+            return None;
+        }
+        let cols = info.window.right - info.window.left + 1;
+        let rows = info.window.bottom - info.window.top + 1;
+        Some((usize::try_from(cols).ok()?, usize::try_from(rows).ok()?))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn console_size() -> Option<(usize, usize)> {
+    None
+}
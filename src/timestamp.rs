@@ -0,0 +1,91 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Default `--timestamp` format: ISO 8601 in UTC with milliseconds.
+pub const DEFAULT_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3fZ";
+
+/// Renders the time at which each line was read.
+pub struct Clock {
+    format: String,
+    start: Instant,
+}
+
+impl Clock {
+    /// `format` is either `elapsed` (seconds since start, monotonic) or a
+    /// strftime-style pattern evaluated in UTC. Supported conversions:
+    /// `%Y %m %d %H %M %S %s %.3f %.6f %.9f %%`.
+    pub fn new(format: &str) -> Self {
+        Clock {
+            format: format.to_string(),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn now(&self) -> String {
+        if self.format == "elapsed" {
+            let elapsed = self.start.elapsed();
+            return format!("{}.{:06}", elapsed.as_secs(), elapsed.subsec_micros());
+        }
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        format_utc(
+            &self.format,
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos(),
+        )
+    }
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) civil date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's days_from_civil inverse
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn format_utc(format: &str, secs: u64, nanos: u32) -> String {
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let time = secs % 86_400;
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&year.to_string()),
+            Some('m') => out.push_str(&format!("{month:02}")),
+            Some('d') => out.push_str(&format!("{day:02}")),
+            Some('H') => out.push_str(&format!("{:02}", time / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", time / 60 % 60)),
+            Some('S') => out.push_str(&format!("{:02}", time % 60)),
+            Some('s') => out.push_str(&secs.to_string()),
+            Some('%') => out.push('%'),
+            Some('.') => match chars.next() {
+                Some('3') if chars.next() == Some('f') => {
+                    out.push_str(&format!(".{:03}", nanos / 1_000_000))
+                }
+                Some('6') if chars.next() == Some('f') => {
+                    out.push_str(&format!(".{:06}", nanos / 1_000))
+                }
+                Some('9') if chars.next() == Some('f') => out.push_str(&format!(".{nanos:09}")),
+                _ => out.push_str("%."),
+            },
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
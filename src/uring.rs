@@ -0,0 +1,438 @@
+use std::collections::VecDeque;
+use std::ffi::{c_int, c_long, c_void};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Reads kept in flight at once.
+const DEPTH: usize = 4;
+
+/// Size of each read.
+const CHUNK: usize = 256 * 1024;
+
+const SYS_IO_URING_SETUP: c_long = 425;
+const SYS_IO_URING_ENTER: c_long = 426;
+const IORING_OFF_SQ_RING: i64 = 0;
+const IORING_OFF_CQ_RING: i64 = 0x800_0000;
+const IORING_OFF_SQES: i64 = 0x1000_0000;
+const IORING_FEAT_SINGLE_MMAP: u32 = 1;
+const IORING_ENTER_GETEVENTS: u32 = 1;
+const IORING_OP_READ: u8 = 22;
+
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const MAP_SHARED: c_int = 1;
+const MAP_POPULATE: c_int = 0x8000;
+const MAP_FAILED: *mut c_void = usize::MAX as *mut c_void;
+
+unsafe extern "C" {
+    fn syscall(number: c_long, ...) -> c_long;
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        off: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    fn close(fd: c_int) -> c_int;
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct SqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqRingOffsets,
+    cq_off: CqRingOffsets,
+}
+
+/// A submission queue entry, as far as reads need it.
+#[repr(C)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    pad: [u64; 3],
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+struct Mapping {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: c_int, len: usize, offset: i64) -> io::Result<Mapping> {
+        // SAFETY: maps a region of the ring fd; the result is checked
+        let ptr = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_SHARED | MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping { ptr, len })
+    }
+
+    /// Pointer to the value `offset` bytes into the mapping.
+    fn at<T>(&self, offset: u32) -> *mut T {
+        // SAFETY: offsets come from the kernel and lie within the mapping
+        unsafe { self.ptr.cast::<u8>().add(offset as usize).cast() }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: unmaps exactly what `new` mapped
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// A minimal io_uring instance: one submission and one completion queue,
+/// used from a single thread.
+struct Ring {
+    fd: c_int,
+    params: Params,
+    sq_ring: Mapping,
+    cq_ring: Option<Mapping>,
+    sqes: Mapping,
+}
+
+impl Ring {
+    fn new(entries: u32) -> io::Result<Ring> {
+        let mut params = Params::default();
+        // SAFETY: io_uring_setup fills in `params`
+        let fd = unsafe { syscall(SYS_IO_URING_SETUP, entries, &mut params as *mut Params) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = fd as c_int;
+        Ring::map(fd, params).inspect_err(|_| {
+            // SAFETY: the ring fd is ours and not used after this
+            unsafe {
+                close(fd);
+            }
+        })
+    }
+
+    fn map(fd: c_int, params: Params) -> io::Result<Ring> {
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len = params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<Cqe>();
+        let single = params.features & IORING_FEAT_SINGLE_MMAP != 0;
+        let sq_ring = Mapping::new(
+            fd,
+            if single { sq_len.max(cq_len) } else { sq_len },
+            IORING_OFF_SQ_RING,
+        )?;
+        let cq_ring = if single {
+            None
+        } else {
+            Some(Mapping::new(fd, cq_len, IORING_OFF_CQ_RING)?)
+        };
+        let sqes = Mapping::new(
+            fd,
+            params.sq_entries as usize * size_of::<Sqe>(),
+            IORING_OFF_SQES,
+        )?;
+        Ok(Ring {
+            fd,
+            params,
+            sq_ring,
+            cq_ring,
+            sqes,
+        })
+    }
+
+    fn cq(&self) -> &Mapping {
+        self.cq_ring.as_ref().unwrap_or(&self.sq_ring)
+    }
+
+    /// Queue a read of `len` bytes at `offset` of `fd` into `buf` and submit it.
+    ///
+    /// # Safety
+    ///
+    /// `buf` must stay valid and untouched until the completion is reaped.
+    unsafe fn submit_read(
+        &mut self,
+        fd: c_int,
+        buf: *mut u8,
+        len: usize,
+        offset: u64,
+        user_data: u64,
+    ) -> io::Result<()> {
+        let off = &self.params.sq_off;
+        // SAFETY: the ring pointers are valid for the life of the mapping and
+        // only this thread submits
+        unsafe {
+            let tail_ref = &*self.sq_ring.at::<AtomicU32>(off.tail);
+            let mask = *self.sq_ring.at::<u32>(off.ring_mask);
+            let tail = tail_ref.load(Ordering::Relaxed);
+            let index = tail & mask;
+            self.sqes.at::<Sqe>(0).add(index as usize).write(Sqe {
+                opcode: IORING_OP_READ,
+                flags: 0,
+                ioprio: 0,
+                fd,
+                off: offset,
+                addr: buf as u64,
+                len: len as u32,
+                rw_flags: 0,
+                user_data,
+                pad: [0; 3],
+            });
+            *self.sq_ring.at::<u32>(off.array).add(index as usize) = index;
+            tail_ref.store(tail.wrapping_add(1), Ordering::Release);
+            self.enter(1, 0)
+        }
+    }
+
+    fn enter(&self, to_submit: u32, min_complete: u32) -> io::Result<()> {
+        loop {
+            // SAFETY: io_uring_enter on our ring with no signal mask
+            let rc = unsafe {
+                syscall(
+                    SYS_IO_URING_ENTER,
+                    self.fd,
+                    to_submit,
+                    min_complete,
+                    IORING_ENTER_GETEVENTS,
+                    ptr::null::<c_void>(),
+                    0usize,
+                )
+            };
+            if rc >= 0 {
+                return Ok(());
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted || crate::interrupt::requested() {
+                return Err(e);
+            }
+        }
+    }
+
+    /// Wait for the next completion and return its user data and result.
+    fn reap(&self) -> io::Result<(u64, i32)> {
+        let off = &self.params.cq_off;
+        let cq = self.cq();
+        // SAFETY: as in `submit_read`; only this thread consumes completions
+        unsafe {
+            let head_ref = &*cq.at::<AtomicU32>(off.head);
+            let tail_ref = &*cq.at::<AtomicU32>(off.tail);
+            let head = head_ref.load(Ordering::Relaxed);
+            while tail_ref.load(Ordering::Acquire) == head {
+                self.enter(0, 1)?;
+            }
+            let mask = *cq.at::<u32>(off.ring_mask);
+            let cqe = cq.at::<Cqe>(off.cqes).add((head & mask) as usize).read();
+            head_ref.store(head.wrapping_add(1), Ordering::Release);
+            Ok((cqe.user_data, cqe.res))
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        // SAFETY: the ring fd is ours; the mappings are dropped after this
+        unsafe {
+            close(self.fd);
+        }
+    }
+}
+
+/// Reader for a regular file that keeps several reads ahead in flight with
+/// io_uring, so the disk is busy while earlier data is being numbered.
+pub struct UringReader<'a> {
+    file: &'a File,
+    ring: Ring,
+    buffers: Vec<Box<[u8]>>,
+    /// File offset each buffer was read from.
+    offsets: Vec<u64>,
+    /// Result of each buffer's read once reaped.
+    results: Vec<Option<i32>>,
+    /// Buffers with a read in flight or data to hand out, in file order.
+    queue: VecDeque<usize>,
+    /// Position within the buffer at the front of the queue.
+    pos: usize,
+    /// File offset of the next read to submit.
+    next_offset: u64,
+    eof: bool,
+}
+
+impl<'a> UringReader<'a> {
+    /// Set up the ring, or fail (e.g. on kernels without io_uring or where
+    /// it is disabled) so the caller can fall back to plain reads.
+    pub fn new(file: &'a File) -> io::Result<UringReader<'a>> {
+        let ring = Ring::new(DEPTH as u32)?;
+        let mut reader = UringReader {
+            file,
+            ring,
+            buffers: (0..DEPTH)
+                .map(|_| vec![0; CHUNK].into_boxed_slice())
+                .collect(),
+            offsets: vec![0; DEPTH],
+            results: vec![None; DEPTH],
+            queue: VecDeque::with_capacity(DEPTH),
+            pos: 0,
+            next_offset: 0,
+            eof: false,
+        };
+        for slot in 0..DEPTH {
+            reader.submit(slot)?;
+        }
+        Ok(reader)
+    }
+
+    fn submit(&mut self, slot: usize) -> io::Result<()> {
+        self.results[slot] = None;
+        self.offsets[slot] = self.next_offset;
+        let buf = self.buffers[slot].as_mut_ptr();
+        // SAFETY: the buffer lives in `self` and is not touched until its
+        // completion is reaped; `Drop` reaps everything still in flight
+        unsafe {
+            self.ring.submit_read(
+                self.file.as_raw_fd(),
+                buf,
+                CHUNK,
+                self.next_offset,
+                slot as u64,
+            )?;
+        }
+        self.next_offset += CHUNK as u64;
+        self.queue.push_back(slot);
+        Ok(())
+    }
+
+    /// Wait until the read of `slot` has completed.
+    fn wait_for(&mut self, slot: usize) -> io::Result<i32> {
+        loop {
+            if let Some(res) = self.results[slot] {
+                return Ok(res);
+            }
+            let (done, res) = self.ring.reap()?;
+            self.results[done as usize] = Some(res);
+        }
+    }
+
+    /// Redo the queued reads from `offset`, after a short read left them
+    /// at the wrong offsets.
+    fn restart_at(&mut self, offset: u64) -> io::Result<()> {
+        let stale: Vec<usize> = self.queue.drain(..).collect();
+        for &slot in &stale {
+            self.wait_for(slot)?;
+        }
+        self.next_offset = offset;
+        for slot in stale {
+            self.submit(slot)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for UringReader<'_> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if crate::interrupt::requested() {
+                return Err(crate::interrupt::error());
+            }
+            let Some(&slot) = self.queue.front() else {
+                return Ok(0);
+            };
+            let res = self.wait_for(slot)?;
+            if res < 0 {
+                return Err(io::Error::from_raw_os_error(-res));
+            }
+            let len = res as usize;
+            if self.pos < len {
+                let n = out.len().min(len - self.pos);
+                out[..n].copy_from_slice(&self.buffers[slot][self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+
+            // The front buffer is used up: reuse it for the next chunk
+            self.queue.pop_front();
+            self.pos = 0;
+            if len == 0 {
+                self.eof = true;
+            }
+            if self.eof {
+                continue;
+            }
+            if len < CHUNK {
+                self.restart_at(self.offsets[slot] + len as u64)?;
+            }
+            self.submit(slot)?;
+        }
+    }
+}
+
+impl Drop for UringReader<'_> {
+    fn drop(&mut self) {
+        // The kernel may still be writing into the buffers
+        while let Some(slot) = self.queue.pop_front() {
+            if self.wait_for(slot).is_err() {
+                // Leak the buffers rather than free memory still in use
+                std::mem::forget(std::mem::take(&mut self.buffers));
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{
+    NumberFormatter, NumberedLine, Numberer, Numbering, NumberingPolicy, SectionClassifier,
+    SectionStyles,
+};
+use std::io::{self, Write};
+
+/// Writer that numbers the lines written through it before passing them on
+/// to `inner`. Lines may arrive in any number of pieces; each is numbered
+/// once its `\n` is written. Call [`finish`](Self::finish) at the end, so a
+/// last line without a `\n` is written too.
+pub struct LineNumberingWriter<W: Write> {
+    inner: W,
+    numberer: Numberer,
+    partial: Vec<u8>, // the start of a line whose end has not been written
+}
+
+impl<W: Write> LineNumberingWriter<W> {
+    /// Number with the styles in `numbering`.
+    pub fn new(inner: W, numbering: Numbering) -> Self {
+        let policy = SectionStyles::from(&numbering);
+        LineNumberingWriter::with_policy(inner, numbering, policy)
+    }
+
+    /// Number the lines `policy` picks, laid out as `numbering` says (its
+    /// styles go unused).
+    pub fn with_policy(
+        inner: W,
+        numbering: Numbering,
+        policy: impl NumberingPolicy + Send + 'static,
+    ) -> Self {
+        LineNumberingWriter {
+            inner,
+            numberer: Numberer::new(numbering, Box::new(policy)),
+            partial: Vec::new(),
+        }
+    }
+
+    /// Tell sections apart with `classifier` rather than by delimiter
+    /// lines.
+    pub fn set_classifier(&mut self, classifier: impl SectionClassifier + Send + 'static) {
+        self.numberer.classifier = Box::new(classifier);
+    }
+
+    /// Render numbers with `formatter` rather than the `Numbering` format.
+    pub fn set_formatter(&mut self, formatter: impl NumberFormatter + Send + 'static) {
+        self.numberer.formatter = Box::new(formatter);
+    }
+
+    /// Call `hook` with each line as it is numbered, to collect line
+    /// numbers or report progress alongside the output.
+    pub fn on_line(&mut self, hook: impl FnMut(&NumberedLine) + Send + 'static) {
+        self.numberer.observer = Some(Box::new(hook));
+    }
+
+    /// Number and write any unfinished last line, then return the inner
+    /// writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.partial.is_empty() {
+            let len = self.partial.len();
+            self.numberer
+                .write_line(&self.partial, len, &mut self.inner)?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for LineNumberingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            if self.partial.is_empty() {
+                self.numberer
+                    .write_line(&rest[..end], end + 1, &mut self.inner)?;
+            } else {
+                self.partial.extend_from_slice(&rest[..end]);
+                let line = std::mem::take(&mut self.partial);
+                self.numberer
+                    .write_line(&line, line.len() + 1, &mut self.inner)?;
+            }
+            self.inner.write_all(b"\n")?;
+            rest = &rest[end + 1..];
+        }
+        self.partial.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    /// Flush the lines written so far. An unfinished line is held back
+    /// until its end arrives, so it is numbered only once.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
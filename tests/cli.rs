@@ -0,0 +1,301 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Run nl with `args`, feeding it `input` on stdin.
+fn nl(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nl"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("nl runs");
+    // nl may exit before reading it all, as on a usage error
+    let written = child.stdin.take().expect("stdin is piped").write_all(input);
+    if let Err(e) = written {
+        assert_eq!(e.kind(), std::io::ErrorKind::BrokenPipe, "input is written");
+    }
+    child.wait_with_output().expect("nl exits")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Write `contents` to a file of this test run named after `name`, and
+/// return its path.
+fn input_file(name: &str, contents: &[u8]) -> String {
+    let path = std::env::temp_dir().join(format!("nl-test-{}-{name}", std::process::id()));
+    std::fs::write(&path, contents).expect("temporary file is written");
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn block_copy_blanks_out_delimiters() {
+    let input = b"a\n\\:\\:\nb\n\\:\nc";
+    let copied = nl(&["-bn", "-hn", "-fn", "-w", "0", "-s", ""], input);
+    assert_eq!(stdout(&copied), "a\n\nb\n\nc\n");
+}
+
+#[test]
+fn block_copy_is_not_taken_for_options_that_change_lines() {
+    let stripped = nl(
+        &["--cat-n", "-bn", "--strip-ansi=output"],
+        b"\x1b[1mx\x1b[0m\n",
+    );
+    assert_eq!(stdout(&stripped), "x\n");
+
+    let warned = nl(
+        &["--cat-n", "-bn", "--warn-invisible"],
+        "\u{200B}\n".as_bytes(),
+    );
+    assert_eq!(warned.status.code(), Some(1));
+
+    let traced = nl(&["--cat-n", "-bn", "--debug"], b"a\n");
+    assert!(String::from_utf8_lossy(&traced.stderr).contains("debug:"));
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn continue_from_input_keeps_padding_of_unnumbered_blank_lines() {
+    let numbered = nl(&[], b"a\n\nb\n");
+    let mut input = numbered.stdout.clone();
+    input.extend_from_slice(b"new\n");
+    let continued = nl(&["--continue-from-input"], &input);
+    assert_eq!(
+        stdout(&continued),
+        "     1\ta\n      \n     2\tb\n     3\tnew\n"
+    );
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn continue_from_input_reads_records_by_the_record_separator() {
+    let args = [
+        "--record-sep=;",
+        "--output-record-sep=;",
+        "--continue-from-input",
+    ];
+    let continued = nl(&args, b"     1\ta;     2\tb;new;");
+    assert_eq!(stdout(&continued), "     1\ta;     2\tb;     3\tnew;");
+}
+
+#[test]
+fn unreadable_inputs_exit_with_1_unless_several_all_failed() {
+    let one = nl(&["/nonexistent/a"], b"");
+    assert_eq!(one.status.code(), Some(1));
+
+    let some = nl(&["/nonexistent/a", "-"], b"x\n");
+    assert_eq!(some.status.code(), Some(1));
+
+    let all = nl(&["/nonexistent/a", "/nonexistent/b"], b"");
+    assert_eq!(all.status.code(), Some(2));
+}
+
+#[test]
+#[cfg(not(feature = "regex"))]
+fn pattern_in_style_expression_reports_missing_regex_support() {
+    let output = nl(&["-b", "t&pFOO"], b"FOO\n");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("style 'p' for '-b' is not supported"));
+}
+
+#[test]
+fn max_memory_limits_lines_whether_or_not_they_are_numbered() {
+    let mut long = vec![b'0'; 5000];
+    long.push(b'\n');
+    for args in [["-bn", "-w", "0"], ["-ba", "-w", "0"]] {
+        let output = nl(&[&args[..], &["--max-memory=1K"]].concat(), &long);
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("record longer than --max-memory"));
+    }
+}
+
+#[test]
+fn files_go_on_in_the_section_and_blank_run_the_one_before_ended_in() {
+    let header = input_file("header", b"\\:\\:\\:\nh1\n");
+    let more = input_file("more-header", b"h2\n");
+    assert_eq!(
+        stdout(&nl(&[&header, &more], b"")),
+        "\n      h1\n      h2\n"
+    );
+
+    let ends_blank = input_file("ends-blank", b"a\n\n");
+    let starts_blank = input_file("starts-blank", b"\nb\n");
+    let joined = nl(&["-ba", "-l", "2", &ends_blank, &starts_blank], b"");
+    assert_eq!(stdout(&joined), "     1\ta\n      \n     2\t\n     3\tb\n");
+}
+
+#[test]
+fn threads_need_restart_per_file() {
+    let output = nl(&["--threads=2", "-"], b"a\n");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'--threads' can only be used with '--restart-per-file'"));
+
+    let output = nl(&["--threads=2", "--restart-per-file", "-"], b"a\n");
+    assert_eq!(stdout(&output), "     1\ta\n");
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn pattern_style_keeps_a_bar_in_its_regex() {
+    let output = nl(&["-b", "pa|t", "-w", "1"], b"x\na\nt\n");
+    assert_eq!(stdout(&output), " x\n1\ta\n2\tt\n");
+
+    let output = nl(&["-b", "t&pFOO|BAR", "-w", "1"], b"FOO\nx\nBAR\n");
+    assert_eq!(stdout(&output), "1\tFOO\n x\n2\tBAR\n");
+}
+
+#[test]
+fn keep_line_endings_cannot_be_used_with_record_sep() {
+    let output = nl(&["--keep-line-endings", "--record-sep=;"], b"a;b;");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'--keep-line-endings' cannot be used with '--record-sep'"));
+}
+
+#[test]
+fn lang_adds_to_comment_prefixes_given_before_it() {
+    let input = b"; note\n// rust\ncode\n";
+    for args in [
+        ["--comment-prefix=;", "--lang=rust"],
+        ["--lang=rust", "--comment-prefix=;"],
+    ] {
+        let output = nl(&[&args[..], &["-w", "1"]].concat(), input);
+        assert_eq!(stdout(&output), " ; note\n // rust\n1\tcode\n");
+    }
+}
+
+#[test]
+fn empty_comment_prefix_is_rejected() {
+    let output = nl(&["--comment-prefix="], b"a\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("empty comment prefix"));
+}
+
+#[test]
+fn show_nonprinting_writes_invalid_utf8_like_cat_v() {
+    let output = nl(&["--show-nonprinting", "-w", "1"], b"\xe9\n\xff\x80x\n");
+    assert_eq!(stdout(&output), "1\tM-i\n2\tM-^?M-^@x\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn json_array_elements_keep_nested_arrays_and_objects_whole() {
+    let input = br#"[ [1, [2]], {"a": {"b": [3]}}, 4 ]"#;
+    let output = nl(&["--json-array", "-w", "1"], input);
+    assert_eq!(
+        stdout(&output),
+        "1\t[1,[2]]\n2\t{\"a\":{\"b\":[3]}}\n3\t4\n"
+    );
+}
+
+#[test]
+fn json_array_strings_may_hold_brackets_and_escaped_quotes() {
+    let input = br#"["x]y", "q\"],\\", "a b"]"#;
+    let output = nl(&["--json-array", "-w", "1"], input);
+    assert_eq!(
+        stdout(&output),
+        "1\t\"x]y\"\n2\t\"q\\\"],\\\\\"\n3\t\"a b\"\n"
+    );
+}
+
+#[test]
+fn json_array_cut_short_is_an_error() {
+    let output = nl(&["--json-array"], b"[1, [2");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid JSON array"));
+}
+
+/// A unified diff with two hunks, and a line past the end of the second.
+const TWO_HUNKS: &[u8] = b"--- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n\
+@@ -10,2 +10,3 @@\n j\n+k\n l\ndiff --git a/g b/g\n";
+
+#[test]
+fn diff_numbers_lines_by_their_place_in_the_new_file() {
+    let output = nl(&["--diff", "-w", "2"], TWO_HUNKS);
+    assert_eq!(
+        stdout(&output),
+        "  --- a/f\n  +++ b/f\n  @@ -1,3 +1,3 @@\n 1\t a\n  -b\n 2\t+B\n 3\t c\n\
+         \x20 @@ -10,2 +10,3 @@\n10\t j\n11\t+k\n12\t l\n  diff --git a/g b/g\n"
+    );
+}
+
+#[test]
+fn diff_added_numbers_only_added_lines() {
+    let output = nl(&["--diff=added", "-w", "2"], TWO_HUNKS);
+    assert_eq!(
+        stdout(&output),
+        "  --- a/f\n  +++ b/f\n  @@ -1,3 +1,3 @@\n   a\n  -b\n 2\t+B\n   c\n\
+         \x20 @@ -10,2 +10,3 @@\n   j\n11\t+k\n   l\n  diff --git a/g b/g\n"
+    );
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn strip_undoes_numbering() {
+    let input = b"a\n\n  indented\nb\n";
+    let numbered = nl(&[], input);
+    let stripped = nl(&["--strip"], &numbered.stdout);
+    assert_eq!(stripped.stdout, input);
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn strip_leaves_lines_without_a_number_field() {
+    let output = nl(&["--strip"], b"     1\ta\nplain\n     2\tb\n");
+    assert_eq!(stdout(&output), "a\nplain\nb\n");
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn check_accepts_nl_output() {
+    let numbered = nl(&[], b"a\n\nb\nc\n");
+    let checked = nl(&["--check"], &numbered.stdout);
+    assert_eq!(checked.status.code(), Some(0));
+    assert!(checked.stderr.is_empty());
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn check_reports_gaps_repeats_and_numbers_out_of_order() {
+    let input = b"     1\ta\n     3\tb\nplain\n     3\tc\n     2\td\n";
+    let checked = nl(&["--check"], input);
+    assert_eq!(checked.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&checked.stderr),
+        "nl: standard input:2: gap before 3, expected 2\n\
+         nl: standard input:4: duplicate number 3 (first on line 2)\n\
+         nl: standard input:5: number 2 out of order, expected 4\n"
+    );
+}
+
+#[test]
+fn csv_records_go_on_through_newlines_in_quoted_fields() {
+    let input = b"id,note\n1,\"two\nlines\"\n2,\"say \"\"hi\"\"\nand bye\"\n3,x\n";
+    let output = nl(&["--csv", "-w", "1"], input);
+    assert_eq!(
+        stdout(&output),
+        "1\tid,note\n2\t1,\"two\n \tlines\"\n3\t2,\"say \"\"hi\"\"\n \tand bye\"\n4\t3,x\n"
+    );
+}
+
+#[test]
+fn markdown_fences_close_only_on_a_matching_fence() {
+    // A shorter fence, or one of the other character, does not close a block
+    let input = b"text\n````\n```\ninner\n````\nafter\n~~~\ntilde ```\n~~~\nend\n";
+    let skipped = nl(&["--markdown", "-w", "1"], input);
+    assert_eq!(
+        stdout(&skipped),
+        "1\ttext\n ````\n ```\n inner\n ````\n2\tafter\n ~~~\n tilde ```\n ~~~\n3\tend\n"
+    );
+    let code = nl(&["--markdown=only-code", "-w", "1"], input);
+    assert_eq!(
+        stdout(&code),
+        " text\n ````\n1\t```\n2\tinner\n ````\n after\n ~~~\n3\ttilde ```\n ~~~\n end\n"
+    );
+}
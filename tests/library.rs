@@ -0,0 +1,270 @@
+use nl::{LineNumberingWriter, Numbering, Style};
+use std::io::Write;
+
+/// `input` written through a `LineNumberingWriter` with `numbering`.
+fn numbered(input: &str, numbering: Numbering) -> String {
+    let mut out = LineNumberingWriter::new(Vec::new(), numbering);
+    out.write_all(input.as_bytes())
+        .expect("writes to a Vec succeed");
+    String::from_utf8(out.finish().expect("writes to a Vec succeed")).expect("input is UTF-8")
+}
+
+#[test]
+fn join_blank_numbers_the_last_of_each_group() {
+    let numbering = Numbering {
+        body: Style::All,
+        join_blank: 2,
+        width: 1,
+        ..Numbering::default()
+    };
+    assert_eq!(
+        numbered("a\n\n\n\nb\n", numbering),
+        "1\ta\n \n2\t\n \n3\tb\n"
+    );
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn pattern_styles_combine_like_the_command() {
+    let warn = Style::Pattern(regex::Regex::new("WARN").unwrap());
+    let numbering = Numbering {
+        body: Style::And(vec![Style::NonEmpty, Style::Not(Box::new(warn))]),
+        width: 1,
+        ..Numbering::default()
+    };
+    assert_eq!(
+        numbered("ok\nWARN x\n\nfine\n", numbering),
+        "1\tok\n WARN x\n \n2\tfine\n"
+    );
+}
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn numbering_types_can_move_to_another_thread() {
+    assert_send::<LineNumberingWriter<Vec<u8>>>();
+    assert_send::<nl::LineNumberingReader<&[u8]>>();
+    assert_send::<nl::Stream<&[u8]>>();
+}
+
+#[test]
+fn numbered_lines_have_a_stable_json_form() {
+    let lines: Vec<_> = nl::stream(&b"say \"hi\"\n\n"[..], Numbering::default())
+        .collect::<Result<_, _>>()
+        .expect("reading a slice succeeds");
+    assert_eq!(
+        lines[0].to_json(),
+        r#"{"number":1,"section":"body","index":0,"offset":0,"text":"say \"hi\""}"#
+    );
+    assert_eq!(
+        lines[1].to_json(),
+        r#"{"number":null,"section":"body","index":1,"offset":9,"text":""}"#
+    );
+}
+
+#[test]
+fn reader_numbers_lines_read_in_small_pieces() {
+    let input = &b"a\n\\:\\:\\:\nhead\n\\:\\:\nb"[..];
+    let mut reader = nl::LineNumberingReader::new(input, Numbering::default());
+    let mut numbered = Vec::new();
+    let mut piece = [0; 3];
+    loop {
+        let n = std::io::Read::read(&mut reader, &mut piece).expect("reading a slice succeeds");
+        if n == 0 {
+            break;
+        }
+        numbered.extend_from_slice(&piece[..n]);
+    }
+    assert_eq!(
+        String::from_utf8(numbered).expect("input is UTF-8"),
+        "     1\ta\n\n      head\n\n     1\tb"
+    );
+}
+
+/// Numbers lines starting with `TODO`, each with its input line number.
+struct TodoLines;
+
+impl nl::NumberingPolicy for TodoLines {
+    fn decide(&mut self, line: &[u8], index: u64, _: nl::Section) -> nl::Decision {
+        if line.starts_with(b"TODO") {
+            nl::Decision::Number(index as i64 + 1)
+        } else {
+            nl::Decision::Skip
+        }
+    }
+}
+
+#[test]
+fn custom_policy_picks_lines_and_their_numbers() {
+    let numbering = Numbering {
+        width: 1,
+        ..Numbering::default()
+    };
+    let mut out = LineNumberingWriter::with_policy(Vec::new(), numbering, TodoLines);
+    out.write_all(b"code\nTODO one\nmore\nTODO two\n")
+        .expect("writes to a Vec succeed");
+    let out = out.finish().expect("writes to a Vec succeed");
+    assert_eq!(
+        String::from_utf8(out).expect("input is UTF-8"),
+        " code\n2\tTODO one\n more\n4\tTODO two\n"
+    );
+}
+
+#[test]
+fn hook_sees_every_line_as_it_is_numbered() {
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut out = LineNumberingWriter::new(Vec::new(), Numbering::default());
+    let hook_seen = seen.clone();
+    out.on_line(move |line| {
+        let entry = (line.number, line.index, line.offset, line.section);
+        hook_seen.lock().unwrap().push(entry);
+    });
+    out.write_all(b"a\n\n\\:\nfoot\n")
+        .expect("writes to a Vec succeed");
+    out.finish().expect("writes to a Vec succeed");
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [
+            (Some(1), 0, 0, nl::Section::Body),
+            (None, 1, 2, nl::Section::Body),
+            (None, 3, 6, nl::Section::Footer),
+        ]
+    );
+}
+
+/// Starts a header at each Markdown heading and a body after it.
+struct Headings;
+
+impl nl::SectionClassifier for Headings {
+    fn classify(&mut self, line: &[u8], _: nl::Section) -> nl::Classified {
+        if line.starts_with(b"# ") {
+            nl::Classified::Line(nl::Section::Header)
+        } else {
+            nl::Classified::Line(nl::Section::Body)
+        }
+    }
+}
+
+#[test]
+fn custom_classifier_chooses_sections() {
+    let numbering = Numbering {
+        header: Style::All,
+        width: 1,
+        ..Numbering::default()
+    };
+    let mut out = LineNumberingWriter::new(Vec::new(), numbering);
+    out.set_classifier(Headings);
+    out.write_all(b"# One\na\nb\n# Two\nc\n\\:\n")
+        .expect("writes to a Vec succeed");
+    let out = out.finish().expect("writes to a Vec succeed");
+    // Numbering restarts at each change of section; delimiters are text
+    assert_eq!(
+        String::from_utf8(out).expect("input is UTF-8"),
+        "1\t# One\n1\ta\n2\tb\n1\t# Two\n1\tc\n2\t\\:\n"
+    );
+}
+
+/// Numbers in lower-case roman numerals, right-aligned in the field.
+struct Roman;
+
+impl nl::NumberFormatter for Roman {
+    fn format(&self, number: i64, width: usize) -> String {
+        let mut rest = number;
+        let mut digits = String::new();
+        for (value, digit) in [(10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i")] {
+            while rest >= value {
+                digits.push_str(digit);
+                rest -= value;
+            }
+        }
+        format!("{digits:>width$}")
+    }
+}
+
+#[test]
+fn custom_formatter_renders_the_numbers() {
+    let numbering = Numbering {
+        body: Style::All,
+        start: 3,
+        width: 4,
+        ..Numbering::default()
+    };
+    let mut out = LineNumberingWriter::new(Vec::new(), numbering);
+    out.set_formatter(Roman);
+    out.write_all(b"a\nb\n\nc\n")
+        .expect("writes to a Vec succeed");
+    let out = out.finish().expect("writes to a Vec succeed");
+    assert_eq!(
+        String::from_utf8(out).expect("input is UTF-8"),
+        " iii\ta\n  iv\tb\n   v\t\n  vi\tc\n"
+    );
+}
+
+/// Gives `data`, then fails.
+struct FailingAfter<'a>(&'a [u8]);
+
+impl std::io::Read for FailingAfter<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.0.is_empty() {
+            return Err(std::io::Error::other("disk on fire"));
+        }
+        let n = self.0.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn stream_yields_structured_lines_without_delimiters() {
+    let lines: Vec<_> = nl::stream(&b"a\n\\:\\:\\:\nh\n\\:\\:\nb\n"[..], Numbering::default())
+        .collect::<Result<_, _>>()
+        .expect("reading a slice succeeds");
+    let summary: Vec<_> = lines
+        .iter()
+        .map(|line| {
+            (
+                &*line.text,
+                line.index,
+                line.offset,
+                line.section,
+                line.number,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (&b"a"[..], 0, 0, nl::Section::Body, Some(1)),
+            (&b"h"[..], 2, 9, nl::Section::Header, None),
+            (&b"b"[..], 4, 16, nl::Section::Body, Some(1)),
+        ]
+    );
+}
+
+#[test]
+fn stream_ends_after_a_read_error() {
+    let mut lines = nl::stream(FailingAfter(b"a\nb"), Numbering::default());
+    assert_eq!(lines.next().unwrap().unwrap().number, Some(1));
+    let error = lines.next().unwrap().unwrap_err();
+    assert_eq!(error.to_string(), "disk on fire");
+    assert!(lines.next().is_none());
+}
+
+#[test]
+fn next_line_lends_the_same_lines_the_iterator_yields() {
+    let input = &b"one\n\ntwo\r\nthree"[..];
+    let owned: Vec<_> = nl::stream(input, Numbering::default())
+        .map(|line| line.expect("reading a slice succeeds"))
+        .collect();
+    let mut lent = Vec::new();
+    let mut lines = nl::stream(input, Numbering::default());
+    while let Some(line) = lines.next_line() {
+        let line = line.expect("reading a slice succeeds");
+        assert!(matches!(line.text, std::borrow::Cow::Borrowed(_)));
+        lent.push(line.into_owned());
+    }
+    assert_eq!(lent, owned);
+    let texts: Vec<_> = lent.iter().map(|line| &*line.text).collect();
+    assert_eq!(texts, [&b"one"[..], b"", b"two\r", b"three"]);
+}